@@ -0,0 +1,534 @@
+// smbc is library wrapping libsmbclient from Samba project
+// Copyright (c) 2016 Konstantin Gribov
+//
+// This file is part of smbc.
+//
+// smbc is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// smbc is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with smbc. If not, see <http://www.gnu.org/licenses/>.
+
+//! NT security descriptors in `libsmbclient` xattr format
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use result::{Error, Result};
+
+/// NT security descriptor: owner, group and discretionary ACL.
+///
+/// Parsed from and formatted to the form `libsmbclient` uses for
+/// `system.nt_sec_desc.*+` attribute, e.g.
+/// `REVISION:1,OWNER:S-1-5-32-544,GROUP:S-1-5-32-545,ACL:S-1-1-0:0/0/0x001f01ff`.
+///
+/// See [`security_descriptor(..)`](struct.SmbClient.html#method.security_descriptor).
+///
+/// ## Examples
+///
+/// ```rust
+/// # fn main() {
+/// #   example().unwrap();
+/// # }
+/// #
+/// # fn example() -> smbc::Result<()> {
+///     let sd: smbc::SecurityDescriptor =
+///         "REVISION:1,OWNER:S-1-5-32-544,ACL:S-1-1-0:0/3/0x001200a9".parse()?;
+///     let admins: smbc::Sid = "S-1-5-32-544".parse()?;
+///     assert_eq!(sd.owner(), Some(&admins));
+///     assert_eq!(sd.group(), None);
+///
+///     let ace = &sd.aces()[0];
+///     assert_eq!(ace.sid().to_string(), "S-1-1-0");
+///     assert_eq!(ace.kind(), smbc::AceType::Allowed);
+///     assert_eq!(ace.mask(), 0x001200a9);
+///
+///     assert_eq!(sd.to_string(), "REVISION:1,OWNER:S-1-5-32-544,ACL:S-1-1-0:0/3/0x001200a9");
+/// #   Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecurityDescriptor {
+    revision: u32,
+    owner: Option<Sid>,
+    group: Option<Sid>,
+    aces: Vec<Ace>,
+}
+
+/// Access control entry of [`SecurityDescriptor`](struct.SecurityDescriptor.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ace {
+    sid: Sid,
+    kind: AceType,
+    flags: u8,
+    mask: u32,
+}
+
+/// Security identifier of user or group like `S-1-5-21-1004336348-1177238915-682003330-512`.
+///
+/// ## Examples
+///
+/// ```rust
+/// # fn main() {
+/// #   example().unwrap();
+/// # }
+/// #
+/// # fn example() -> smbc::Result<()> {
+///     let sid: smbc::Sid = "S-1-5-32-544".parse()?;
+///     assert_eq!(sid.authority(), 5);
+///     assert_eq!(sid.sub_authorities(), &[32, 544]);
+///     assert_eq!(sid.rid(), Some(544));
+///     assert_eq!(sid, smbc::Sid::new(5, &[32, 544]));
+/// #   Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Sid {
+    revision: u8,
+    authority: u64,
+    sub_authorities: Vec<u32>,
+}
+
+/// Type of [`Ace`](struct.Ace.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AceType {
+    /// Grants access
+    Allowed,
+    /// Denies access
+    Denied,
+    /// Audits access (system ACL)
+    Audit,
+    /// Raises alarm on access (system ACL)
+    Alarm,
+}
+
+/// Pair SIDs of `sd` with trustees of the same descriptor in named form
+/// (`system.nt_sec_desc.*` value with SIDs resolved to `DOMAIN\name`).
+///
+/// Owner, group and ACEs are matched by position, SIDs `libsmbclient`
+/// couldn't resolve (left numeric in named form) are skipped.
+pub(crate) fn sid_names(sd: &SecurityDescriptor, named: &str) -> Result<Vec<(Sid, String)>> {
+    let mut owner = None;
+    let mut group = None;
+    let mut trustees = Vec::new();
+    for field in named.trim().split(',').filter(|f| !f.is_empty()) {
+        let sep = field.find(':').ok_or_else(|| invalid("field", field))?;
+        let (key, value) = (&field[..sep], &field[sep + 1..]);
+        match key {
+            "OWNER" => owner = Some(value),
+            "GROUP" => group = Some(value),
+            "ACL" => {
+                let sep = value.rfind(':').ok_or_else(|| invalid("ACE", value))?;
+                trustees.push(&value[..sep]);
+            }
+            _ => {}
+        }
+    }
+
+    let mut names = Vec::new();
+    let mut add = |sid: &Sid, name: &str| {
+        if name.parse::<Sid>().is_err() {
+            names.push((sid.clone(), name.to_owned()));
+        }
+    };
+    if let (Some(sid), Some(name)) = (sd.owner(), owner) {
+        add(sid, name);
+    }
+    if let (Some(sid), Some(name)) = (sd.group(), group) {
+        add(sid, name);
+    }
+    for (ace, name) in sd.aces().iter().zip(trustees) {
+        add(ace.sid(), name);
+    }
+    Ok(names)
+}
+
+fn invalid(what: &str, value: &str) -> Error {
+    let msg = format!("invalid {} in security descriptor: {:?}", what, value);
+    io::Error::new(io::ErrorKind::InvalidData, msg).into()
+}
+
+// SecurityDescriptor {{{1
+impl SecurityDescriptor {
+    // {{{2
+    /// Empty descriptor of revision 1 without owner, group and ACEs.
+    ///
+    /// Owner and group which are `None` are left unchanged when
+    /// descriptor is set.
+    pub fn new() -> SecurityDescriptor {
+        SecurityDescriptor {
+            revision: 1,
+            owner: None,
+            group: None,
+            aces: Vec::new(),
+        }
+    }
+
+    /// Revision of descriptor format (usually `1`).
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// Owner SID if server returned it.
+    pub fn owner(&self) -> Option<&Sid> {
+        self.owner.as_ref()
+    }
+
+    /// Primary group SID if server returned it.
+    pub fn group(&self) -> Option<&Sid> {
+        self.group.as_ref()
+    }
+
+    /// Entries of discretionary ACL in order.
+    pub fn aces(&self) -> &[Ace] {
+        &self.aces
+    }
+
+    /// Set revision of descriptor format.
+    pub fn set_revision(&mut self, revision: u32) {
+        self.revision = revision;
+    }
+
+    /// Set owner SID, `None` keeps owner unchanged on server.
+    pub fn set_owner(&mut self, owner: Option<Sid>) {
+        self.owner = owner;
+    }
+
+    /// Set primary group SID, `None` keeps group unchanged on server.
+    pub fn set_group(&mut self, group: Option<Sid>) {
+        self.group = group;
+    }
+
+    /// Append `ace` to ACL unless equal entry is already there.
+    ///
+    /// `libsmbclient` puts denied entries before allowed ones when
+    /// descriptor is set, so order of calls doesn't matter.
+    pub fn add_ace(&mut self, ace: Ace) {
+        if !self.aces.contains(&ace) {
+            self.aces.push(ace);
+        }
+    }
+
+    /// Remove entries equal to `ace` (same SID, type, flags and mask),
+    /// returns `false` if there were none.
+    pub fn remove_ace(&mut self, ace: &Ace) -> bool {
+        let len = self.aces.len();
+        self.aces.retain(|a| a != ace);
+        self.aces.len() != len
+    }
+} // 2}}}
+
+impl Default for SecurityDescriptor {
+    fn default() -> SecurityDescriptor {
+        SecurityDescriptor::new()
+    }
+}
+
+impl FromStr for SecurityDescriptor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SecurityDescriptor> {
+        let mut sd = SecurityDescriptor::new();
+        for field in s.trim().split(',').filter(|f| !f.is_empty()) {
+            let sep = field.find(':').ok_or_else(|| invalid("field", field))?;
+            let (key, value) = (&field[..sep], &field[sep + 1..]);
+            match key {
+                "REVISION" => {
+                    sd.revision = value.parse().map_err(|_| invalid("revision", value))?;
+                }
+                "OWNER" => sd.owner = Some(value.parse()?),
+                "GROUP" => sd.group = Some(value.parse()?),
+                "ACL" => sd.aces.push(value.parse()?),
+                _ => return Err(invalid("field", field)),
+            }
+        }
+        Ok(sd)
+    }
+}
+
+impl fmt::Display for SecurityDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "REVISION:{}", self.revision)?;
+        if let Some(ref owner) = self.owner {
+            write!(f, ",OWNER:{}", owner)?;
+        }
+        if let Some(ref group) = self.group {
+            write!(f, ",GROUP:{}", group)?;
+        }
+        for ace in &self.aces {
+            write!(f, ",ACL:{}", ace)?;
+        }
+        Ok(())
+    }
+}
+// 1}}}
+
+// Ace {{{1
+impl Ace {
+    // {{{2
+    /// Entry of type `kind` granting or denying `mask` to `sid`
+    /// with inheritance `flags`.
+    pub fn new(sid: Sid, kind: AceType, flags: u8, mask: u32) -> Ace {
+        Ace {
+            sid,
+            kind,
+            flags,
+            mask,
+        }
+    }
+
+    /// SID entry applies to.
+    pub fn sid(&self) -> &Sid {
+        &self.sid
+    }
+
+    /// Entry type.
+    pub fn kind(&self) -> AceType {
+        self.kind
+    }
+
+    /// Inheritance flags (`OBJECT_INHERIT_ACE` etc.).
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Access mask (`FILE_READ_DATA`, `GENERIC_ALL` etc.).
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+} // 2}}}
+
+impl FromStr for Ace {
+    type Err = Error;
+
+    /// Parses `sid:type/flags/mask` with decimal type and flags and hex mask.
+    fn from_str(s: &str) -> Result<Ace> {
+        let sep = s.rfind(':').ok_or_else(|| invalid("ACE", s))?;
+        let (sid, rest) = (&s[..sep], &s[sep + 1..]);
+        let mut parts = rest.split('/');
+        let (kind, flags, mask) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(kind), Some(flags), Some(mask), None) => (kind, flags, mask),
+            _ => return Err(invalid("ACE", s)),
+        };
+        let kind = match kind {
+            "0" | "ALLOWED" => AceType::Allowed,
+            "1" | "DENIED" => AceType::Denied,
+            "2" => AceType::Audit,
+            "3" => AceType::Alarm,
+            _ => return Err(invalid("ACE type", kind)),
+        };
+        let flags = flags.parse().map_err(|_| invalid("ACE flags", flags))?;
+        let hex = mask.trim_start_matches("0x").trim_start_matches("0X");
+        let mask = u32::from_str_radix(hex, 16).map_err(|_| invalid("ACE mask", mask))?;
+        Ok(Ace {
+            sid: sid.parse()?,
+            kind,
+            flags,
+            mask,
+        })
+    }
+}
+
+impl fmt::Display for Ace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            AceType::Allowed => 0,
+            AceType::Denied => 1,
+            AceType::Audit => 2,
+            AceType::Alarm => 3,
+        };
+        write!(
+            f,
+            "{}:{}/{}/0x{:08x}",
+            self.sid, kind, self.flags, self.mask
+        )
+    }
+}
+// 1}}}
+
+// Sid {{{1
+impl Sid {
+    // {{{2
+    /// Maximum number of sub-authorities in SID.
+    pub const MAX_SUB_AUTHORITIES: usize = 15;
+
+    /// SID of revision 1 with identifier `authority` and `sub_authorities`.
+    ///
+    /// Extra sub-authorities above `MAX_SUB_AUTHORITIES` are dropped.
+    pub fn new(authority: u64, sub_authorities: &[u32]) -> Sid {
+        let len = sub_authorities.len().min(Sid::MAX_SUB_AUTHORITIES);
+        Sid {
+            revision: 1,
+            authority: authority & 0xffff_ffff_ffff,
+            sub_authorities: sub_authorities[..len].to_vec(),
+        }
+    }
+
+    /// SID revision (always `1` in practice).
+    pub fn revision(&self) -> u8 {
+        self.revision
+    }
+
+    /// 48-bit identifier authority (e.g. `5` for `NT AUTHORITY`).
+    pub fn authority(&self) -> u64 {
+        self.authority
+    }
+
+    /// Sub-authorities in order.
+    pub fn sub_authorities(&self) -> &[u32] {
+        &self.sub_authorities
+    }
+
+    /// Relative identifier (last sub-authority) if SID has any.
+    pub fn rid(&self) -> Option<u32> {
+        self.sub_authorities.last().cloned()
+    }
+} // 2}}}
+
+impl FromStr for Sid {
+    type Err = Error;
+
+    /// Parses `S-1-<authority>-<sub>-...` with decimal or `0x` hex authority.
+    fn from_str(s: &str) -> Result<Sid> {
+        let mut parts = s.trim().split('-');
+        match parts.next() {
+            Some("S") | Some("s") => {}
+            _ => return Err(invalid("SID", s)),
+        }
+        let revision = parts
+            .next()
+            .and_then(|r| r.parse().ok())
+            .ok_or_else(|| invalid("SID", s))?;
+        let authority = parts.next().ok_or_else(|| invalid("SID", s))?;
+        let authority = if authority.starts_with("0x") || authority.starts_with("0X") {
+            u64::from_str_radix(&authority[2..], 16)
+        } else {
+            authority.parse()
+        };
+        let authority = match authority {
+            Ok(authority) if authority <= 0xffff_ffff_ffff => authority,
+            _ => return Err(invalid("SID", s)),
+        };
+        let sub_authorities = parts
+            .map(|sub| sub.parse().map_err(|_| invalid("SID", s)))
+            .collect::<Result<Vec<u32>>>()?;
+        if sub_authorities.len() > Sid::MAX_SUB_AUTHORITIES {
+            return Err(invalid("SID", s));
+        }
+        Ok(Sid {
+            revision,
+            authority,
+            sub_authorities,
+        })
+    }
+}
+
+impl fmt::Display for Sid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "S-{}-", self.revision)?;
+        // authorities above 32 bits are conventionally written in hex
+        if self.authority >> 32 == 0 {
+            write!(f, "{}", self.authority)?;
+        } else {
+            write!(f, "0x{:012X}", self.authority)?;
+        }
+        for sub in &self.sub_authorities {
+            write!(f, "-{}", sub)?;
+        }
+        Ok(())
+    }
+}
+// 1}}}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn security_descriptor_round_trip() {
+        let value = "REVISION:1,OWNER:S-1-5-32-544,GROUP:S-1-5-32-545,\
+                     ACL:S-1-1-0:0/0/0x001f01ff,ACL:S-1-5-11:1/3/0x00000001";
+        let sd: SecurityDescriptor = value.parse().unwrap();
+        assert_eq!(sd.revision(), 1);
+        assert_eq!(sd.owner(), Some(&Sid::new(5, &[32, 544])));
+        assert_eq!(sd.group(), Some(&Sid::new(5, &[32, 545])));
+        assert_eq!(
+            sd.aces(),
+            &[
+                Ace::new(Sid::new(1, &[0]), AceType::Allowed, 0, 0x001f_01ff),
+                Ace::new(Sid::new(5, &[11]), AceType::Denied, 3, 1),
+            ]
+        );
+        assert_eq!(sd.to_string(), value);
+        assert_eq!(sd.to_string().parse::<SecurityDescriptor>().unwrap(), sd);
+    }
+
+    #[test]
+    fn security_descriptor_rejects_garbage() {
+        assert!("REVISION:x".parse::<SecurityDescriptor>().is_err());
+        assert!("OWNER".parse::<SecurityDescriptor>().is_err());
+        assert!("MODE:0x20".parse::<SecurityDescriptor>().is_err());
+        assert_eq!(
+            "".parse::<SecurityDescriptor>().unwrap(),
+            SecurityDescriptor::new()
+        );
+    }
+
+    #[test]
+    fn ace_parse() {
+        let ace: Ace = "S-1-1-0:ALLOWED/0/0x1200a9".parse().unwrap();
+        assert_eq!(ace.kind(), AceType::Allowed);
+        assert_eq!(ace.mask(), 0x0012_00a9);
+        assert_eq!(ace.to_string(), "S-1-1-0:0/0/0x001200a9");
+        assert!("S-1-1-0:0/0".parse::<Ace>().is_err());
+        assert!("S-1-1-0:0/0/0x1/2".parse::<Ace>().is_err());
+        assert!("S-1-1-0:4/0/0x1".parse::<Ace>().is_err());
+        assert!("S-1-1-0:0/x/0x1".parse::<Ace>().is_err());
+        assert!("0/0/0x1".parse::<Ace>().is_err());
+    }
+
+    #[test]
+    fn sid_parse() {
+        let sid: Sid = "s-1-0x123456789ABC-1-2".parse().unwrap();
+        assert_eq!(sid.authority(), 0x1234_5678_9abc);
+        assert_eq!(sid.to_string(), "S-1-0x123456789ABC-1-2");
+        assert_eq!(Sid::new(5, &[]).rid(), None);
+        assert!("S-1".parse::<Sid>().is_err());
+        assert!("X-1-5".parse::<Sid>().is_err());
+        assert!("S-1-0x1000000000000".parse::<Sid>().is_err());
+        assert!("S-1-5-1-2-3-4-5-6-7-8-9-10-11-12-13-14-15-16"
+            .parse::<Sid>()
+            .is_err());
+    }
+
+    #[test]
+    fn sid_names_skip_unresolved() {
+        let sd: SecurityDescriptor =
+            "REVISION:1,OWNER:S-1-5-32-544,ACL:S-1-1-0:0/0/0x1,ACL:S-1-5-21-1-2-3-500:0/0/0x1"
+                .parse()
+                .unwrap();
+        let named = "REVISION:1,OWNER:BUILTIN\\Administrators,\
+                     ACL:\\Everyone:0/0/0x1,ACL:S-1-5-21-1-2-3-500:0/0/0x1";
+        let names = sid_names(&sd, named).unwrap();
+        assert_eq!(
+            names,
+            vec![
+                (
+                    Sid::new(5, &[32, 544]),
+                    "BUILTIN\\Administrators".to_owned()
+                ),
+                (Sid::new(1, &[0]), "\\Everyone".to_owned()),
+            ]
+        );
+    }
+}
+
+// vim: fen:fdm=marker:fdl=1:
@@ -0,0 +1,388 @@
+// smbc is library wrapping libsmbclient from Samba project
+// Copyright (c) 2016 Konstantin Gribov
+//
+// This file is part of smbc.
+//
+// smbc is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// smbc is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with smbc. If not, see <http://www.gnu.org/licenses/>.
+
+//! Buffered I/O over SMB files
+
+use std::cmp;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::ptr;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+use result::{Error, Result};
+use smbc::{SmbClient, SmbFile};
+
+/// Default buffer size of `SmbBufFile`
+const DEFAULT_BUF_SIZE: usize = 64 * 1024;
+
+/// Chunks `SmbReadAhead` reads before caller consumes them
+const READ_AHEAD_DEPTH: usize = 1;
+
+/// [`SmbFile`](struct.SmbFile.html) with read and write buffers.
+///
+/// Each `read` or `write` of `SmbFile` is a round trip to server,
+/// `SmbBufFile` reads ahead in chunks of its capacity and coalesces
+/// small writes, so `BufRead` methods like `lines()` and many small
+/// writes are usable over share.
+///
+/// Only one buffer holds data at a time: pending writes are flushed before
+/// reading and unread buffered data is dropped (with file position moved back)
+/// before writing or seeking. Pending writes are flushed when `SmbBufFile`
+/// is dropped, but errors are ignored then, so call `flush()` or
+/// [`into_inner()`](struct.SmbBufFile.html#method.into_inner) to see them.
+///
+/// ## Examples
+///
+/// ```rust
+/// # fn main() {}
+/// #
+/// use std::io::BufRead;
+///
+/// fn grep(client: &smbc::SmbClient, path: &str, needle: &str) -> smbc::Result<Vec<String>> {
+///     let file = smbc::SmbBufFile::new(client.open(path)?);
+///     let mut found = Vec::new();
+///     for line in file.lines() {
+///         let line = line?;
+///         if line.contains(needle) {
+///             found.push(line);
+///         }
+///     }
+///     Ok(found)
+/// }
+/// ```
+pub struct SmbBufFile<'a: 'b, 'b> {
+    inner: SmbFile<'a, 'b>,
+    /// Read buffer, `rbuf[rpos..rlen]` isn't consumed yet
+    rbuf: Vec<u8>,
+    rpos: usize,
+    rlen: usize,
+    /// Data written but not sent to server yet
+    wbuf: Vec<u8>,
+}
+
+// SmbBufFile {{{1
+impl<'a, 'b> SmbBufFile<'a, 'b> {
+    // {{{2
+    /// Wrap `file` with buffers of default capacity (64 KiB).
+    pub fn new(file: SmbFile<'a, 'b>) -> SmbBufFile<'a, 'b> {
+        SmbBufFile::with_capacity(DEFAULT_BUF_SIZE, file)
+    }
+
+    /// Wrap `file` with buffers of `capacity` bytes (`0` is treated as `1`).
+    ///
+    /// Larger buffers mean fewer round trips, server usually accepts
+    /// reads and writes up to several megabytes.
+    pub fn with_capacity(capacity: usize, file: SmbFile<'a, 'b>) -> SmbBufFile<'a, 'b> {
+        let capacity = cmp::max(capacity, 1);
+        SmbBufFile {
+            inner: file,
+            rbuf: vec![0u8; capacity],
+            rpos: 0,
+            rlen: 0,
+            wbuf: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Buffer capacity in bytes.
+    pub fn capacity(&self) -> usize {
+        self.rbuf.len()
+    }
+
+    /// Wrapped file.
+    pub fn get_ref(&self) -> &SmbFile<'a, 'b> {
+        &self.inner
+    }
+
+    /// Wrapped file.
+    ///
+    /// Its position is ahead of buffered reader or behind pending writes,
+    /// so reading, writing or seeking it directly may confuse `SmbBufFile`.
+    pub fn get_mut(&mut self) -> &mut SmbFile<'a, 'b> {
+        &mut self.inner
+    }
+
+    /// Flush pending writes and unwrap file.
+    ///
+    /// File position is moved back to the first unread byte.
+    pub fn into_inner(mut self) -> Result<SmbFile<'a, 'b>> {
+        self.flush_writes()?;
+        self.discard_reads()?;
+        // `SmbBufFile` has `Drop`, so file is moved out without running it
+        let mut this = mem::ManuallyDrop::new(self);
+        drop(mem::take(&mut this.rbuf));
+        drop(mem::take(&mut this.wbuf));
+        Ok(unsafe { ptr::read(&this.inner) })
+    }
+
+    /// Send pending writes to server.
+    fn flush_writes(&mut self) -> io::Result<()> {
+        let mut written = 0;
+        let mut res = Ok(());
+        while written < self.wbuf.len() {
+            match self.inner.write(&self.wbuf[written..]) {
+                Ok(0) => {
+                    res = Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write buffered data",
+                    ));
+                    break;
+                }
+                Ok(n) => written += n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    res = Err(err);
+                    break;
+                }
+            }
+        }
+        // written part isn't retried
+        self.wbuf.drain(..written);
+        res
+    }
+
+    /// Drop unread buffered data moving file position back to it.
+    fn discard_reads(&mut self) -> io::Result<()> {
+        let unread = self.rlen - self.rpos;
+        if unread > 0 {
+            self.inner.seek(SeekFrom::Current(-(unread as i64)))?;
+        }
+        self.rpos = 0;
+        self.rlen = 0;
+        Ok(())
+    }
+} // 2}}}
+
+impl<'a, 'b> Read for SmbBufFile<'a, 'b> {
+    // {{{2
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // large reads bypass empty buffer
+        if self.rpos == self.rlen && buf.len() >= self.capacity() {
+            self.flush_writes()?;
+            return self.inner.read(buf);
+        }
+        let len = {
+            let available = self.fill_buf()?;
+            let len = cmp::min(available.len(), buf.len());
+            buf[..len].copy_from_slice(&available[..len]);
+            len
+        };
+        self.consume(len);
+        Ok(len)
+    }
+} // 2}}}
+
+impl<'a, 'b> BufRead for SmbBufFile<'a, 'b> {
+    // {{{2
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.rpos == self.rlen {
+            self.flush_writes()?;
+            self.rlen = self.inner.read(&mut self.rbuf)?;
+            self.rpos = 0;
+        }
+        Ok(&self.rbuf[self.rpos..self.rlen])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.rpos = cmp::min(self.rpos + amt, self.rlen);
+    }
+} // 2}}}
+
+impl<'a, 'b> Write for SmbBufFile<'a, 'b> {
+    // {{{2
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.discard_reads()?;
+        if self.wbuf.len() + buf.len() > self.capacity() {
+            self.flush_writes()?;
+        }
+        if buf.len() >= self.capacity() {
+            self.inner.write(buf)
+        } else {
+            self.wbuf.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_writes()?;
+        self.inner.flush()
+    }
+} // 2}}}
+
+impl<'a, 'b> Seek for SmbBufFile<'a, 'b> {
+    // {{{2
+    /// Flushes pending writes and drops read buffer, position is relative
+    /// to logical position (as seen by caller).
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.flush_writes()?;
+        self.discard_reads()?;
+        self.inner.seek(pos)
+    }
+} // 2}}}
+
+impl<'a, 'b> Drop for SmbBufFile<'a, 'b> {
+    fn drop(&mut self) {
+        // errors can't be reported from `drop`
+        let _ = self.flush_writes();
+    }
+}
+// 1}}}
+
+/// Sequential reader of SMB file which reads next chunk in background thread
+/// while caller consumes current one.
+///
+/// `SmbFile` can't leave thread of its `SmbClient`, so `SmbReadAhead` takes
+/// client and opens file in its own worker thread. Chunks are
+/// [`io_chunk_size`](struct.SmbClientBuilder.html#method.io_chunk_size)
+/// bytes, so round trips to server overlap with caller's processing,
+/// which helps streaming (e.g. media) throughput.
+///
+/// Dropping reader stops worker after its current read.
+///
+/// ```rust
+/// # use std::io;
+/// # fn main() {}
+/// #
+/// fn stream(client: smbc::SmbClient<'static>, path: &str) -> smbc::Result<u64> {
+///     let mut reader = smbc::SmbReadAhead::open(client, path)?;
+///     let stdout = io::stdout();
+///     Ok(io::copy(&mut reader, &mut stdout.lock())?)
+/// }
+/// ```
+pub struct SmbReadAhead {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    /// Chunk being consumed, `current[pos..]` isn't read yet
+    current: Vec<u8>,
+    pos: usize,
+    eof: bool,
+    worker: Option<JoinHandle<()>>,
+}
+
+// SmbReadAhead {{{1
+impl SmbReadAhead {
+    // {{{2
+    /// Open file at SMB `path` with `client` in worker thread.
+    ///
+    /// Fails if file can't be opened, later errors are returned by `read`.
+    pub fn open<P: AsRef<str>>(client: SmbClient<'static>, path: P) -> Result<SmbReadAhead> {
+        let path = path.as_ref().to_owned();
+        let (opened_tx, opened_rx) = mpsc::sync_channel(1);
+        let (chunks_tx, chunks) = mpsc::sync_channel(READ_AHEAD_DEPTH);
+
+        let worker = thread::spawn(move || {
+            let mut file = match client.open(&path) {
+                Ok(file) => file,
+                Err(err) => {
+                    let _ = opened_tx.send(Err(err));
+                    return;
+                }
+            };
+            let _ = opened_tx.send(Ok(()));
+            let chunk_size = client.io_chunk_size();
+            loop {
+                let mut chunk = Vec::with_capacity(chunk_size);
+                let res = file.read_uninit(&mut chunk, chunk_size);
+                let last = match res {
+                    Ok(n) => n == 0,
+                    Err(_) => true,
+                };
+                // empty chunk marks end of file; send fails if reader is dropped
+                if chunks_tx.send(res.map(|_| chunk)).is_err() || last {
+                    break;
+                }
+            }
+            trace!(target: "smbc", "read-ahead of {} stopped", path);
+        });
+
+        let opened = opened_rx
+            .recv()
+            .unwrap_or_else(|_| Err(Error::Io(worker_panicked())));
+        if let Err(err) = opened {
+            let _ = worker.join();
+            return Err(err);
+        }
+        Ok(SmbReadAhead {
+            chunks,
+            current: Vec::new(),
+            pos: 0,
+            eof: false,
+            worker: Some(worker),
+        })
+    }
+} // 2}}}
+
+impl Read for SmbReadAhead {
+    // {{{2
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = {
+            let available = self.fill_buf()?;
+            let len = cmp::min(available.len(), buf.len());
+            buf[..len].copy_from_slice(&available[..len]);
+            len
+        };
+        self.consume(len);
+        Ok(len)
+    }
+} // 2}}}
+
+impl BufRead for SmbReadAhead {
+    // {{{2
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.current.len() && !self.eof {
+            match self.chunks.recv() {
+                Ok(Ok(chunk)) => {
+                    self.eof = chunk.is_empty();
+                    self.current = chunk;
+                    self.pos = 0;
+                }
+                Ok(Err(err)) => {
+                    self.eof = true;
+                    return Err(err);
+                }
+                Err(_) => {
+                    self.eof = true;
+                    return Err(worker_panicked());
+                }
+            }
+        }
+        Ok(&self.current[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = cmp::min(self.pos + amt, self.current.len());
+    }
+} // 2}}}
+
+impl Drop for SmbReadAhead {
+    fn drop(&mut self) {
+        // worker sees disconnected channel after its current read
+        let (_, disconnected) = mpsc::sync_channel(0);
+        drop(mem::replace(&mut self.chunks, disconnected));
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+// 1}}}
+
+/// Error for channel of `SmbReadAhead` worker closed without end of file
+fn worker_panicked() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "read-ahead worker panicked")
+}
+
+// vim: fen:fdm=marker:fdl=1:
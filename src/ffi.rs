@@ -0,0 +1,257 @@
+// smbc is library wrapping libsmbclient from Samba project
+// Copyright (c) 2016 Konstantin Gribov
+//
+// This file is part of smbc.
+//
+// smbc is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// smbc is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with smbc. If not, see <http://www.gnu.org/licenses/>.
+
+//! Bindings to `libsmbclient` functions missing from `smbclient-sys`
+
+#![allow(non_camel_case_types, non_snake_case)]
+
+use std::mem;
+use std::ptr;
+
+use libc::{c_char, c_int, c_uint, c_ulong, c_void, gid_t, off_t, size_t, timespec, uid_t};
+
+use smbclient_sys::{smbc_bool, SMBCCTX, SMBCFILE};
+
+// `smbc_dirent.smbc_type` values (private in `smbclient-sys`)
+pub const SMBC_WORKGROUP: c_uint = 1;
+pub const SMBC_SERVER: c_uint = 2;
+pub const SMBC_FILE_SHARE: c_uint = 3;
+pub const SMBC_PRINTER_SHARE: c_uint = 4;
+pub const SMBC_COMMS_SHARE: c_uint = 5;
+pub const SMBC_IPC_SHARE: c_uint = 6;
+pub const SMBC_DIR: c_uint = 7;
+pub const SMBC_FILE: c_uint = 8;
+pub const SMBC_LINK: c_uint = 9;
+
+// `smbc_notify_callback_action.action` values
+pub const SMBC_NOTIFY_ACTION_ADDED: u32 = 1;
+pub const SMBC_NOTIFY_ACTION_REMOVED: u32 = 2;
+pub const SMBC_NOTIFY_ACTION_MODIFIED: u32 = 3;
+pub const SMBC_NOTIFY_ACTION_OLD_NAME: u32 = 4;
+pub const SMBC_NOTIFY_ACTION_NEW_NAME: u32 = 5;
+pub const SMBC_NOTIFY_ACTION_ADDED_STREAM: u32 = 6;
+pub const SMBC_NOTIFY_ACTION_REMOVED_STREAM: u32 = 7;
+pub const SMBC_NOTIFY_ACTION_MODIFIED_STREAM: u32 = 8;
+
+#[repr(C)]
+pub struct smbc_notify_callback_action {
+    pub action: u32,
+    pub filename: *const c_char,
+}
+
+/// Returns non-zero to stop watching
+pub type smbc_notify_callback_fn = Option<
+    extern "C" fn(
+        actions: *const smbc_notify_callback_action,
+        num_actions: size_t,
+        private_data: *mut c_void,
+    ) -> c_int,
+>;
+
+pub type smbc_debug_callback_fn =
+    Option<extern "C" fn(private_ptr: *mut c_void, level: c_int, msg: *const c_char)>;
+
+/// Progress of splice with bytes copied so far, returns zero to abort
+pub type smbc_splice_cb = Option<extern "C" fn(n: off_t, private: *mut c_void) -> c_int>;
+
+pub type smbc_splice_fn = Option<
+    extern "C" fn(
+        c: *mut SMBCCTX,
+        srcfile: *mut SMBCFILE,
+        dstfile: *mut SMBCFILE,
+        count: off_t,
+        splice_cb: smbc_splice_cb,
+        private: *mut c_void,
+    ) -> off_t,
+>;
+
+pub type smbc_notify_fn = Option<
+    extern "C" fn(
+        c: *mut SMBCCTX,
+        dir: *mut SMBCFILE,
+        recursive: smbc_bool,
+        completion_filter: u32,
+        callback_timeout_ms: c_uint,
+        cb: smbc_notify_callback_fn,
+        private_data: *mut c_void,
+    ) -> c_int,
+>;
+
+/// `struct statvfs` as `libsmbclient` fills it
+///
+/// `smbclient-sys` binds statvfs functions to `nix`'s `Statvfs` which lacks
+/// glibc's trailing spare fields, so `libsmbclient` would write past its end.
+#[cfg(not(target_os = "macos"))]
+#[repr(C)]
+pub struct statvfs {
+    pub f_bsize: c_ulong,
+    pub f_frsize: c_ulong,
+    pub f_blocks: u64,
+    pub f_bfree: u64,
+    pub f_bavail: u64,
+    pub f_files: u64,
+    pub f_ffree: u64,
+    pub f_favail: u64,
+    pub f_fsid: c_ulong,
+    pub f_flag: c_ulong,
+    pub f_namemax: c_ulong,
+    __f_spare: [c_int; 6],
+}
+
+/// `struct statvfs` as `libsmbclient` fills it (32-bit counters on macOS)
+#[cfg(target_os = "macos")]
+#[repr(C)]
+pub struct statvfs {
+    pub f_bsize: c_ulong,
+    pub f_frsize: c_ulong,
+    pub f_blocks: c_uint,
+    pub f_bfree: c_uint,
+    pub f_bavail: c_uint,
+    pub f_files: c_uint,
+    pub f_ffree: c_uint,
+    pub f_favail: c_uint,
+    pub f_fsid: c_ulong,
+    pub f_flag: c_ulong,
+    pub f_namemax: c_ulong,
+}
+
+/// Entry returned by `readdirplus` (`struct libsmb_file_info`)
+#[repr(C)]
+pub struct libsmb_file_info {
+    pub size: u64,
+    /// DOS attributes
+    pub attrs: u16,
+    pub uid: uid_t,
+    pub gid: gid_t,
+    pub btime_ts: timespec,
+    pub mtime_ts: timespec,
+    pub atime_ts: timespec,
+    pub ctime_ts: timespec,
+    pub name: *mut c_char,
+    pub short_name: *mut c_char,
+}
+
+pub type smbc_readdirplus_fn =
+    Option<extern "C" fn(c: *mut SMBCCTX, dir: *mut SMBCFILE) -> *const libsmb_file_info>;
+
+pub type smbc_statvfs_fn =
+    Option<extern "C" fn(c: *mut SMBCCTX, path: *mut c_char, st: *mut statvfs) -> c_int>;
+
+pub type smbc_fstatvfs_fn =
+    Option<extern "C" fn(c: *mut SMBCCTX, file: *mut SMBCFILE, st: *mut statvfs) -> c_int>;
+
+/// Handle of `dlsym` searching all loaded libraries
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+const RTLD_DEFAULT: *mut c_void = -2isize as *mut c_void;
+#[cfg(not(any(target_os = "macos", target_os = "freebsd")))]
+const RTLD_DEFAULT: *mut c_void = ptr::null_mut();
+
+#[cfg_attr(target_os = "linux", link(name = "dl"))]
+extern "C" {
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+}
+
+/// Address of `libsmbclient` function `name` (NUL-terminated)
+/// or null if installed version doesn't have it.
+///
+/// Functions added by newer Samba releases are looked up at runtime,
+/// so crate still links against older ones.
+fn optional_fn(name: &[u8]) -> *mut c_void {
+    unsafe { dlsym(RTLD_DEFAULT, name.as_ptr() as *const c_char) }
+}
+
+pub type smbc_setLogCallback_fn =
+    unsafe extern "C" fn(c: *mut SMBCCTX, private_ptr: *mut c_void, fn_: smbc_debug_callback_fn);
+
+/// Route debug output to `fn` instead of stdout/stderr (since Samba 4.9)
+pub fn smbc_setLogCallback() -> Option<smbc_setLogCallback_fn> {
+    unsafe { mem::transmute(optional_fn(b"smbc_setLogCallback\0")) }
+}
+
+pub type smbc_getFunctionNotify_fn = unsafe extern "C" fn(c: *mut SMBCCTX) -> smbc_notify_fn;
+
+/// Watch directory for changes (since Samba 4.7)
+pub fn smbc_getFunctionNotify() -> Option<smbc_getFunctionNotify_fn> {
+    unsafe { mem::transmute(optional_fn(b"smbc_getFunctionNotify\0")) }
+}
+
+pub type smbc_getFunctionSplice_fn = unsafe extern "C" fn(c: *mut SMBCCTX) -> smbc_splice_fn;
+
+/// Server-side copy between files (since Samba 4.2)
+pub fn smbc_getFunctionSplice() -> Option<smbc_getFunctionSplice_fn> {
+    unsafe { mem::transmute(optional_fn(b"smbc_getFunctionSplice\0")) }
+}
+
+pub type smbc_getFunctionReaddirPlus_fn =
+    unsafe extern "C" fn(c: *mut SMBCCTX) -> smbc_readdirplus_fn;
+
+/// Read directory entry with its attributes (since Samba 4.5)
+pub fn smbc_getFunctionReaddirPlus() -> Option<smbc_getFunctionReaddirPlus_fn> {
+    unsafe { mem::transmute(optional_fn(b"smbc_getFunctionReaddirPlus\0")) }
+}
+
+pub type smbc_setOptionProtocols_fn = unsafe extern "C" fn(
+    c: *mut SMBCCTX,
+    min_proto: *const c_char,
+    max_proto: *const c_char,
+) -> smbc_bool;
+
+/// Set allowed protocol range, `NULL` leaves bound unchanged (since Samba 4.3)
+pub fn smbc_setOptionProtocols() -> Option<smbc_setOptionProtocols_fn> {
+    unsafe { mem::transmute(optional_fn(b"smbc_setOptionProtocols\0")) }
+}
+
+extern "C" {
+    /// Same as `smbclient_sys::smbc_getFunctionStatVFS` with correct `statvfs` layout,
+    /// use qualified to avoid ambiguity
+    pub fn smbc_getFunctionStatVFS(c: *mut SMBCCTX) -> smbc_statvfs_fn;
+
+    /// Same as `smbclient_sys::smbc_getFunctionFstatVFS` with correct `statvfs` layout,
+    /// use qualified to avoid ambiguity
+    pub fn smbc_getFunctionFstatVFS(c: *mut SMBCCTX) -> smbc_fstatvfs_fn;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optional_fn_lookup() {
+        assert!(!optional_fn(b"malloc\0").is_null());
+        assert!(optional_fn(b"smbc_noSuchFunction\0").is_null());
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    fn file_info_layout() {
+        let info: libsmb_file_info = unsafe { mem::zeroed() };
+        let base = &info as *const _ as usize;
+        let offset = |field: *const u8| field as usize - base;
+        // offsets of C struct on LP64 Linux
+        assert_eq!(mem::size_of::<libsmb_file_info>(), 104);
+        assert_eq!(offset(&info.attrs as *const _ as *const u8), 8);
+        assert_eq!(offset(&info.uid as *const _ as *const u8), 12);
+        assert_eq!(offset(&info.gid as *const _ as *const u8), 16);
+        assert_eq!(offset(&info.btime_ts as *const _ as *const u8), 24);
+        assert_eq!(offset(&info.mtime_ts as *const _ as *const u8), 40);
+        assert_eq!(offset(&info.atime_ts as *const _ as *const u8), 56);
+        assert_eq!(offset(&info.ctime_ts as *const _ as *const u8), 72);
+        assert_eq!(offset(&info.name as *const _ as *const u8), 88);
+        assert_eq!(offset(&info.short_name as *const _ as *const u8), 96);
+    }
+}
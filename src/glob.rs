@@ -0,0 +1,284 @@
+// smbc is library wrapping libsmbclient from Samba project
+// Copyright (c) 2016 Konstantin Gribov
+//
+// This file is part of smbc.
+//
+// smbc is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// smbc is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with smbc. If not, see <http://www.gnu.org/licenses/>.
+
+//! Wildcard matching over SMB paths
+
+use result::Result;
+use smbc::{EntryType, SmbClient, Walk};
+use url::percent_decode;
+
+const SMB_SCHEME: &str = "smb://";
+
+/// Iterator over SMB URLs matching glob pattern.
+///
+/// See [`glob(..)`](struct.SmbClient.html#method.glob).
+pub struct Glob<'a: 'b, 'b> {
+    smbc: &'b SmbClient<'a>,
+    /// Pattern without wildcards, checked for existence only
+    literal: Option<String>,
+    walk: Option<Walk<'a, 'b>>,
+    /// Patterns for path components under base directory
+    patterns: Vec<Vec<char>>,
+    /// Names of components of current entry relative to base directory
+    names: Vec<Vec<char>>,
+}
+
+// Glob {{{1
+impl<'a, 'b> Glob<'a, 'b> {
+    // {{{2
+    pub(crate) fn new(smbc: &'b SmbClient<'a>, pattern: &str) -> Result<Glob<'a, 'b>> {
+        let mut glob = Glob {
+            smbc,
+            literal: None,
+            walk: None,
+            patterns: Vec::new(),
+            names: Vec::new(),
+        };
+        if !pattern.starts_with(SMB_SCHEME) {
+            // let `exists` report invalid URL
+            glob.literal = Some(pattern.to_owned());
+            return Ok(glob);
+        }
+
+        let components: Vec<&str> = pattern[SMB_SCHEME.len()..]
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .collect();
+        let base_len = match components.iter().position(|c| has_wildcards(c)) {
+            Some(idx) => idx,
+            None => {
+                glob.literal = Some(pattern.to_owned());
+                return Ok(glob);
+            }
+        };
+
+        let base = format!("{}{}", SMB_SCHEME, components[..base_len].join("/"));
+        for component in &components[base_len..] {
+            glob.patterns
+                .push(percent_decode(component)?.chars().collect());
+        }
+        let mut walk = smbc.walk(&base);
+        if !glob.patterns.iter().any(|p| is_recursive(p)) {
+            walk = walk.max_depth(glob.patterns.len());
+        }
+        trace!(target: "smbc", "globbing {:?} under {}", &components[base_len..], base);
+        glob.walk = Some(walk);
+        Ok(glob)
+    }
+} // 2}}}
+
+impl<'a, 'b> Iterator for Glob<'a, 'b> {
+    // {{{2
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Result<String>> {
+        if let Some(literal) = self.literal.take() {
+            return match self.smbc.exists(&literal) {
+                Ok(true) => Some(Ok(literal)),
+                Ok(false) => None,
+                Err(err) => Some(Err(err)),
+            };
+        }
+
+        let walk = self.walk.as_mut()?;
+        loop {
+            let (depth, entry, _) = match walk.next()? {
+                Ok(item) => item,
+                Err(err) => return Some(Err(err)),
+            };
+            self.names.truncate(depth - 1);
+            self.names.push(entry.name().chars().collect());
+
+            let is_dir = matches!(
+                entry.entry_type(),
+                EntryType::Dir | EntryType::Share(_) | EntryType::Link
+            );
+            if is_dir && !match_prefix(&self.patterns, &self.names) {
+                walk.skip_current_dir();
+            }
+            if match_path(&self.patterns, &self.names) {
+                return Some(Ok(entry.path().to_owned()));
+            }
+        }
+    }
+} // 2}}}
+  // 1}}}
+
+fn has_wildcards(component: &str) -> bool {
+    component.contains(&['*', '?', '['][..])
+}
+
+/// `**` matches any number of path components
+fn is_recursive(pattern: &[char]) -> bool {
+    pattern == ['*', '*']
+}
+
+/// Check if all `names` match `patterns`
+fn match_path(patterns: &[Vec<char>], names: &[Vec<char>]) -> bool {
+    match patterns.split_first() {
+        None => names.is_empty(),
+        Some((pattern, rest)) if is_recursive(pattern) => {
+            match_path(rest, names) || (!names.is_empty() && match_path(patterns, &names[1..]))
+        }
+        Some((pattern, rest)) => match names.split_first() {
+            Some((name, names)) => match_name(pattern, name) && match_path(rest, names),
+            None => false,
+        },
+    }
+}
+
+/// Check if `names` could be continued to path matching `patterns`
+fn match_prefix(patterns: &[Vec<char>], names: &[Vec<char>]) -> bool {
+    match (patterns.split_first(), names.split_first()) {
+        (_, None) => true,
+        (None, Some(_)) => false,
+        (Some((pattern, _)), Some(_)) if is_recursive(pattern) => true,
+        (Some((pattern, patterns)), Some((name, names))) => {
+            match_name(pattern, name) && match_prefix(patterns, names)
+        }
+    }
+}
+
+/// Match single component: `*`, `?` and `[a-z]`/`[!a-z]` classes,
+/// ASCII case-insensitively as Windows servers compare names.
+fn match_name(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|idx| match_name(rest, &name[idx..])),
+        Some(('?', rest)) => !name.is_empty() && match_name(rest, &name[1..]),
+        Some(('[', rest)) => match (match_class(rest, name.first().cloned()), name.split_first()) {
+            (Some((true, rest)), Some((_, name))) => match_name(rest, name),
+            (Some(_), _) => false,
+            // no closing bracket, so it's just a character
+            (None, _) => literal_eq('[', name) && match_name(rest, &name[1..]),
+        },
+        Some((&c, rest)) => literal_eq(c, name) && match_name(rest, &name[1..]),
+    }
+}
+
+fn literal_eq(c: char, name: &[char]) -> bool {
+    match name.first() {
+        Some(n) => n.eq_ignore_ascii_case(&c),
+        None => false,
+    }
+}
+
+/// Match `c` against class after `[`, returns match result and rest of pattern
+/// or `None` if class isn't closed.
+fn match_class(class: &[char], c: Option<char>) -> Option<(bool, &[char])> {
+    let (negated, class) = match class.split_first() {
+        Some(('!', rest)) | Some(('^', rest)) => (true, rest),
+        _ => (false, class),
+    };
+    let c = c.map(|c| c.to_ascii_lowercase());
+    let mut matched = false;
+    let mut idx = 0;
+    // `]` right after `[` (or `[!`) is literal
+    while idx < class.len() && (idx == 0 || class[idx] != ']') {
+        let lo = class[idx].to_ascii_lowercase();
+        let (hi, next) = if idx + 2 < class.len() && class[idx + 1] == '-' && class[idx + 2] != ']'
+        {
+            (class[idx + 2].to_ascii_lowercase(), idx + 3)
+        } else {
+            (lo, idx + 1)
+        };
+        match c {
+            Some(c) if lo <= c && c <= hi => matched = true,
+            _ => {}
+        }
+        idx = next;
+    }
+    if idx < class.len() {
+        Some((matched != negated, &class[idx + 1..]))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    fn components(path: &str) -> Vec<Vec<char>> {
+        path.split('/')
+            .filter(|c| !c.is_empty())
+            .map(chars)
+            .collect()
+    }
+
+    fn name_matches(pattern: &str, name: &str) -> bool {
+        match_name(&chars(pattern), &chars(name))
+    }
+
+    #[test]
+    fn match_name_wildcards() {
+        assert!(name_matches("*.txt", "a.txt"));
+        assert!(name_matches("*.txt", ".txt"));
+        assert!(!name_matches("*.txt", "a.txt.bak"));
+        assert!(name_matches("a?c", "abc"));
+        assert!(!name_matches("a?c", "ac"));
+        assert!(name_matches("*", ""));
+        assert!(name_matches("a*b*c", "aXXbYYc"));
+        // case-insensitive like Windows servers
+        assert!(name_matches("REPORT*.TXT", "report-1.txt"));
+    }
+
+    #[test]
+    fn match_name_classes() {
+        assert!(name_matches("[a-c]x", "bx"));
+        assert!(name_matches("[a-c]x", "Bx"));
+        assert!(!name_matches("[a-c]x", "dx"));
+        assert!(name_matches("[!a-c]x", "dx"));
+        assert!(!name_matches("[^a-c]x", "ax"));
+        assert!(name_matches("[]]", "]"));
+        assert!(name_matches("[a-]", "-"));
+        assert!(!name_matches("[ab]", ""));
+        // unclosed class is literal
+        assert!(name_matches("[ab", "[ab"));
+        assert!(!name_matches("[ab", "a"));
+    }
+
+    #[test]
+    fn match_path_recursive() {
+        let patterns = components("**/*.rs");
+        assert!(match_path(&patterns, &components("a.rs")));
+        assert!(match_path(&patterns, &components("src/a/b.rs")));
+        assert!(!match_path(&patterns, &components("src/a")));
+        let patterns = components("src/*/mod.rs");
+        assert!(match_path(&patterns, &components("src/x/mod.rs")));
+        assert!(!match_path(&patterns, &components("src/mod.rs")));
+        assert!(!match_path(&patterns, &components("src/x/y/mod.rs")));
+    }
+
+    #[test]
+    fn match_prefix_prunes_dirs() {
+        let patterns = components("src/*/mod.rs");
+        assert!(match_prefix(&patterns, &components("src")));
+        assert!(match_prefix(&patterns, &components("src/x")));
+        assert!(!match_prefix(&patterns, &components("doc")));
+        assert!(!match_prefix(&patterns, &components("src/x/mod.rs/y")));
+        assert!(match_prefix(&components("**/x"), &components("a/b/c")));
+        assert!(has_wildcards("a[0-9]") && !has_wildcards("plain"));
+    }
+}
+
+// vim: fen:fdm=marker:fdl=1:
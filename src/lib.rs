@@ -20,7 +20,8 @@
 //!
 //! It provides basic `std::fs`-like API to access SMB/CIFS file shares
 //!
-//! Primary entrypoint is [`SmbClient`](struct.SmbClient.html) struct.
+//! Primary entrypoint is [`SmbClient`](struct.SmbClient.html) struct,
+//! it could be configured with [`SmbClientBuilder`](struct.SmbClientBuilder.html).
 //!
 //! Files are represented by [`SmbFile`](struct.SmbFile.html).
 
@@ -32,11 +33,34 @@ extern crate smbclient_sys;
 #[macro_use]
 mod util;
 
+/// Bindings missing from `smbclient-sys`
+mod ffi;
+
+/// NT security descriptors (ACLs)
+pub mod acl;
+
+/// Buffered I/O over SMB files
+pub mod buffered;
+
+/// Wildcard matching over SMB paths
+pub mod glob;
+
 /// Module with smbc's Result and Error coercions
 pub mod result;
 
 /// Main API module (reexported later)
 pub mod smbc;
 
+/// Mirroring directory trees between local filesystem and SMB share
+pub mod sync;
+
+/// SMB URL helpers
+pub mod url;
+
+pub use acl::*;
+pub use buffered::*;
+pub use glob::*;
 pub use result::*;
 pub use smbc::*;
+pub use sync::*;
+pub use url::*;
@@ -16,6 +16,8 @@
 // You should have received a copy of the GNU General Public License
 // along with smbc. If not, see <http://www.gnu.org/licenses/>.
 
+use libc;
+
 use std::error;
 use std::ffi;
 use std::fmt;
@@ -29,7 +31,101 @@ pub enum Error {
     NewContext(io::Error),
     InitContext(io::Error),
     NulInPath(ffi::NulError),
+    /// Path isn't valid `smb://` URL
+    InvalidUrl(String),
+    /// File or directory not found (`ENOENT`)
+    NotFound,
+    /// Access denied by server (`EACCES` or `EPERM`)
+    PermissionDenied,
+    /// File or directory already exists (`EEXIST`)
+    AlreadyExists,
+    /// Path component isn't a directory (`ENOTDIR`)
+    NotADirectory,
+    /// Directory was given where file is expected (`EISDIR`)
+    IsADirectory,
+    /// Server refused connection (`ECONNREFUSED`)
+    ConnectionRefused,
+    /// Operation isn't supported by server or share (`EOPNOTSUPP`)
+    NotSupported,
+    /// Rename across shares or servers, copy and delete instead (`EXDEV`)
+    CrossShare,
+    /// Auth function failed with given message
+    AuthFailed(String),
+    /// Incompatible `OpenOptions` combination (e.g. truncate without write)
+    InvalidOpenOptions(String),
+    /// Any other IO error
     Io(io::Error),
+    /// Error of operation on given path during recursive operation
+    Path(String, Box<Error>),
+}
+
+impl Error {
+    /// Check if file or directory wasn't found.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn read_or_create(client: &smbc::SmbClient, path: &str) -> smbc::Result<()> {
+    ///     match client.open(path) {
+    ///         Err(ref err) if err.is_not_found() => client.create(path).map(|_| ()),
+    ///         res => res.map(|_| ()),
+    ///     }
+    /// }
+    /// ```
+    pub fn is_not_found(&self) -> bool {
+        match *self {
+            Error::NotFound => true,
+            Error::Path(_, ref err) => err.is_not_found(),
+            _ => self.io_kind() == Some(io::ErrorKind::NotFound),
+        }
+    }
+
+    /// Check if access was denied (including failed authentication).
+    pub fn is_permission_denied(&self) -> bool {
+        match *self {
+            Error::PermissionDenied | Error::AuthFailed(_) => true,
+            Error::Path(_, ref err) => err.is_permission_denied(),
+            _ => self.io_kind() == Some(io::ErrorKind::PermissionDenied),
+        }
+    }
+
+    /// Check if file or directory already exists.
+    pub fn is_already_exists(&self) -> bool {
+        match *self {
+            Error::AlreadyExists => true,
+            Error::Path(_, ref err) => err.is_already_exists(),
+            _ => self.io_kind() == Some(io::ErrorKind::AlreadyExists),
+        }
+    }
+
+    /// Kind of wrapped `io::Error` if any.
+    fn io_kind(&self) -> Option<io::ErrorKind> {
+        match *self {
+            Error::Io(ref err) | Error::NewContext(ref err) | Error::InitContext(ref err) => {
+                Some(err.kind())
+            }
+            _ => None,
+        }
+    }
+
+    /// Classifies `errno` of failed `libsmbclient` call
+    /// into specific variants falling back to `Error::Io`.
+    ///
+    /// Only used for native calls, other `io::Error`s (e.g. of local files)
+    /// are kept intact in `Error::Io`.
+    pub(crate) fn from_native(err: io::Error) -> Self {
+        match err.raw_os_error() {
+            Some(libc::ENOENT) => Error::NotFound,
+            Some(libc::EACCES) | Some(libc::EPERM) => Error::PermissionDenied,
+            Some(libc::EEXIST) => Error::AlreadyExists,
+            Some(libc::ENOTDIR) => Error::NotADirectory,
+            Some(libc::EISDIR) => Error::IsADirectory,
+            Some(libc::ECONNREFUSED) => Error::ConnectionRefused,
+            Some(libc::EOPNOTSUPP) => Error::NotSupported,
+            Some(libc::EXDEV) => Error::CrossShare,
+            _ => Error::Io(err),
+        }
+    }
 }
 
 impl fmt::Display for Error {
@@ -39,6 +135,18 @@ impl fmt::Display for Error {
             Error::InitContext(ref err) => write!(f, "Init context error: {}", err),
             Error::Io(ref err) => write!(f, "IO error: {}", err),
             Error::NulInPath(ref err) => write!(f, "NUL in path: {}", err),
+            Error::InvalidUrl(ref msg) => write!(f, "Invalid URL: {}", msg),
+            Error::NotFound => write!(f, "Not found"),
+            Error::PermissionDenied => write!(f, "Permission denied"),
+            Error::AlreadyExists => write!(f, "Already exists"),
+            Error::NotADirectory => write!(f, "Not a directory"),
+            Error::IsADirectory => write!(f, "Is a directory"),
+            Error::ConnectionRefused => write!(f, "Connection refused"),
+            Error::NotSupported => write!(f, "Not supported"),
+            Error::CrossShare => write!(f, "Cross-share rename"),
+            Error::AuthFailed(ref msg) => write!(f, "Auth failed: {}", msg),
+            Error::InvalidOpenOptions(ref msg) => write!(f, "Invalid open options: {}", msg),
+            Error::Path(ref path, ref err) => write!(f, "{}: {}", path, err),
         }
     }
 }
@@ -50,6 +158,18 @@ impl error::Error for Error {
             Error::InitContext(ref err) => Some(err),
             Error::Io(ref err) => Some(err),
             Error::NulInPath(ref err) => Some(err),
+            Error::Path(_, ref err) => Some(&**err),
+            Error::NotFound
+            | Error::PermissionDenied
+            | Error::AlreadyExists
+            | Error::NotADirectory
+            | Error::IsADirectory
+            | Error::ConnectionRefused
+            | Error::NotSupported
+            | Error::CrossShare
+            | Error::InvalidUrl(_)
+            | Error::AuthFailed(_)
+            | Error::InvalidOpenOptions(_) => None,
         }
     }
 }
@@ -65,3 +185,47 @@ impl From<ffi::NulError> for Error {
         Error::NulInPath(err)
     }
 }
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) | Error::NewContext(err) | Error::InitContext(err) => err,
+            Error::NulInPath(err) => io::Error::new(io::ErrorKind::InvalidInput, err),
+            Error::InvalidUrl(msg) => io::Error::new(io::ErrorKind::InvalidInput, msg),
+            Error::NotFound => io::Error::from_raw_os_error(libc::ENOENT),
+            Error::PermissionDenied => io::Error::from_raw_os_error(libc::EACCES),
+            Error::AlreadyExists => io::Error::from_raw_os_error(libc::EEXIST),
+            Error::NotADirectory => io::Error::from_raw_os_error(libc::ENOTDIR),
+            Error::IsADirectory => io::Error::from_raw_os_error(libc::EISDIR),
+            Error::ConnectionRefused => io::Error::from_raw_os_error(libc::ECONNREFUSED),
+            Error::NotSupported => io::Error::from_raw_os_error(libc::EOPNOTSUPP),
+            Error::CrossShare => io::Error::from_raw_os_error(libc::EXDEV),
+            Error::AuthFailed(msg) => io::Error::new(io::ErrorKind::PermissionDenied, msg),
+            Error::InvalidOpenOptions(msg) => io::Error::new(io::ErrorKind::InvalidInput, msg),
+            Error::Path(_, err) => (*err).into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_errno_is_classified() {
+        let err = Error::from_native(io::Error::from_raw_os_error(libc::ENOENT));
+        assert!(matches!(err, Error::NotFound));
+        let err = Error::from_native(io::Error::from_raw_os_error(libc::ERANGE));
+        assert!(matches!(err, Error::Io(_)));
+    }
+
+    #[test]
+    fn other_io_error_is_kept() {
+        let err: Error = io::Error::new(io::ErrorKind::NotFound, "/tmp/missing").into();
+        assert!(err.is_not_found());
+        match err {
+            Error::Io(ref err) => assert_eq!(err.to_string(), "/tmp/missing"),
+            _ => panic!("{:?} isn't Error::Io", err),
+        }
+    }
+}
@@ -19,25 +19,97 @@
 //! `smbc` is wrapper library around `libsmbclient` from Samba project.
 
 // imports {{{1
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
 use std::default::Default;
+use std::fmt;
+use std::hash::Hasher;
 use std::io;
 use std::mem;
+use std::ops::BitOr;
 use std::panic;
+use std::process;
 use std::ptr;
+use std::slice;
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::vec;
 
+use std::any::Any;
 use std::borrow::Cow;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fs;
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
 
-use libc::{self, c_char, c_int, c_void, mode_t, off_t};
+use libc::{self, c_char, c_int, c_uint, c_void, mode_t, off_t, size_t};
 
-use result::Result;
+use acl::{self, Ace, SecurityDescriptor, Sid};
+use ffi;
+use ffi::*;
+use glob::Glob;
+use result::{Error, Result};
 use smbclient_sys::*;
+use sync::{same_content, ChangeSet, SyncDirection, SyncOptions};
+use url::{percent_decode, percent_encode_to};
 use util::*;
 // 1}}}
 
 const SMBC_FALSE: smbc_bool = 0;
 const SMBC_TRUE: smbc_bool = 1;
 
+/// Most verbose Samba debug level
+const MAX_DEBUG_LEVEL: u32 = 10;
+
+fn to_smbc_bool(b: bool) -> smbc_bool {
+    if b {
+        SMBC_TRUE
+    } else {
+        SMBC_FALSE
+    }
+}
+
+/// `setxattr` flag: fail if attribute already exists
+const SMBC_XATTR_FLAG_CREATE: c_int = 0x1;
+/// `setxattr` flag: fail if attribute does not exist
+const SMBC_XATTR_FLAG_REPLACE: c_int = 0x2;
+
+/// DOS attributes as hex bitmask, e.g. `0x21`
+const DOS_ATTR_MODE_XATTR: &str = "system.dos_attr.mode";
+
+/// DOS attributes, size and timestamps as `MODE:0x21,SIZE:0,...`
+const DOS_ATTR_ALL_XATTR: &str = "system.dos_attr.*";
+
+/// Whole NT security descriptor with numeric SIDs
+const NT_SEC_DESC_XATTR: &str = "system.nt_sec_desc.*+";
+
+/// Whole NT security descriptor with SIDs resolved to `DOMAIN\name`
+const NT_SEC_DESC_NAMES_XATTR: &str = "system.nt_sec_desc.*";
+
+/// Default chunk size used to stream file content
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How many temporary names `write_atomic` tries before giving up
+const TEMP_MAX_ATTEMPTS: usize = 16;
+
+/// Makes temporary names unique within process
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Initial buffer size for xattr values
+const XATTR_BUF_SIZE: usize = 1024;
+/// How many times xattr buffer is grown before giving up
+const XATTR_MAX_RETRIES: usize = 8;
+
+/// Most entries `read_tree` loads before giving up
+const TREE_MAX_NODES: usize = 100_000;
+
 // types {{{1
 // {{{2
 /// ## Basic info
@@ -76,11 +148,248 @@ const SMBC_TRUE: smbc_bool = 1;
 /// # }
 /// ```
 ///
+/// ## Threads
+///
+/// `SmbClient` is `Send` so it can be moved to another thread
+/// (e.g. into worker or thread pool task), but it isn't `Sync`:
+/// `libsmbclient` context should be used by a single thread at a time.
+///
+/// ```rust
+/// # use std::borrow::Cow;
+/// # use std::thread;
+/// #
+/// # fn main() {}
+/// #
+/// fn auth(_srv: &str, _shr: &str) -> (Cow<'static, str>, Cow<'static, str>, Cow<'static, str>) {
+///     (Cow::Borrowed("WORKGROUP"), Cow::Borrowed("guest"), Cow::Borrowed(""))
+/// }
+///
+/// fn example() -> smbc::Result<()> {
+///     let client = smbc::SmbClient::new(&auth)?;
+///     let worker = thread::spawn(move || -> smbc::Result<()> {
+///         let _file = client.open("smb://127.0.0.1/share/path/to/file")?;
+///         Ok(())
+///     });
+///     worker.join().unwrap()
+/// }
+/// ```
 // 2}}}
 pub struct SmbClient<'a> {
     ctx: *mut SMBCCTX,
-    #[allow(dead_code)]
-    auth_fn: &'a dyn for<'b> Fn(&'b str, &'b str) -> (Cow<'a, str>, Cow<'a, str>, Cow<'a, str>),
+    auth: Box<AuthData<'a>>,
+    chunk_size: usize,
+    /// Options client was built with, see `try_clone`
+    config: SmbClientBuilder,
+}
+
+/// Auth function in fallible form with error converted to message
+type AuthFn<'a> = dyn for<'b> Fn(
+        &'b str,
+        &'b str,
+    ) -> ::std::result::Result<(Cow<'a, str>, Cow<'a, str>, Cow<'a, str>), String>
+    + Sync
+    + 'a;
+
+/// Context user data: auth function and failure of its last call
+struct AuthData<'a> {
+    auth_fn: Arc<AuthFn<'a>>,
+    failure: Mutex<Option<String>>,
+    /// Built with fallible auth function, anonymous login is disabled
+    fallible: bool,
+    default_workgroup: String,
+}
+
+// `SMBCCTX` isn't bound to thread which created it, it just shouldn't be used
+// concurrently. `SmbClient` is `!Sync` and every `SmbFile` borrows it,
+// so context is only reachable from thread owning `SmbClient`.
+//
+// Context user data points to boxed `AuthData` (not to `SmbClient`),
+// so moving `SmbClient` doesn't invalidate it, and `auth_fn` is `Sync`
+// so calling it from other thread (or from clients sharing it
+// after `try_clone`) is fine.
+unsafe impl<'a> Send for SmbClient<'a> {}
+
+/// Builder for [`SmbClient`](struct.SmbClient.html).
+///
+/// Accumulates options which should be applied to `libsmbclient` context
+/// before it's initialized, [`build(..)`](struct.SmbClientBuilder.html#method.build)
+/// creates context, applies them and initializes it.
+///
+/// [`SmbClient::new(..)`](struct.SmbClient.html#method.new) is the same as
+/// `SmbClientBuilder::default().build(..)`.
+///
+/// ## Examples
+///
+/// ```rust
+/// # use std::borrow::Cow;
+/// # use std::time::Duration;
+/// #
+/// # fn main() {}
+/// #
+/// # fn example() -> smbc::Result<()> {
+/// # let auth = |host: &str, share: &str| {
+/// #    (Cow::Borrowed("WORKGROUP"), Cow::Borrowed("test"), Cow::Borrowed("Pa$$w0rd1"))
+/// # };
+///     let client = smbc::SmbClientBuilder::default()
+///         .min_protocol(smbc::SmbProtocol::SMB2)
+///         .encryption_level(smbc::EncryptionLevel::Require)
+///         .timeout(Duration::from_secs(30))
+///         .build(&auth)?;
+/// #   Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct SmbClientBuilder {
+    default_workgroup: String,
+    one_share_per_server: bool,
+    use_kerberos: bool,
+    debug_level: u32,
+    debug_to_stderr: bool,
+    debug_to_log: bool,
+    min_protocol: Option<SmbProtocol>,
+    max_protocol: Option<SmbProtocol>,
+    encryption_level: Option<EncryptionLevel>,
+    share_mode: Option<ShareMode>,
+    timeout: Option<Duration>,
+    case_sensitive: Option<bool>,
+    full_time_names: Option<bool>,
+    chunk_size: usize,
+}
+
+/// Metadata of file or directory, similar to `std::fs::Metadata`.
+///
+/// See [`metadata(..)`](struct.SmbClient.html#method.metadata).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    size: u64,
+    mode: mode_t,
+    accessed: SystemTime,
+    modified: SystemTime,
+    changed: SystemTime,
+}
+
+/// POSIX permission bits of file or directory, similar to `std::fs::Permissions`.
+///
+/// Unless share has Unix extensions `libsmbclient` maps them
+/// from and to DOS attributes, so effectively only read-only flag
+/// (all write bits unset) is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Permissions {
+    mode: mode_t,
+}
+
+/// Type of file system entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    /// Regular file
+    File,
+    /// Directory
+    Dir,
+    /// Symbolic link (shares with Unix extensions only)
+    Symlink,
+    /// Anything else
+    Other,
+}
+
+/// File system statistics of share, similar to `statvfs(3)`.
+///
+/// See [`statvfs(..)`](struct.SmbClient.html#method.statvfs)
+/// and [`SmbFile::fstatvfs()`](struct.SmbFile.html#method.fstatvfs).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsStats {
+    block_size: u64,
+    fragment_size: u64,
+    blocks: u64,
+    blocks_free: u64,
+    blocks_available: u64,
+    files: u64,
+    files_free: u64,
+    capabilities: FsCapabilities,
+}
+
+/// Share capabilities reported by `libsmbclient` in `statvfs` flags.
+///
+/// See [`FsStats::capabilities()`](struct.FsStats.html#method.capabilities).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FsCapabilities(u32);
+
+/// Share exported by server.
+///
+/// See [`list_shares(..)`](struct.SmbClient.html#method.list_shares).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShareInfo {
+    name: String,
+    kind: ShareKind,
+    comment: String,
+}
+
+/// Workgroup (domain) found by network browsing.
+///
+/// See [`list_workgroups()`](struct.SmbClient.html#method.list_workgroups).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkgroupInfo {
+    name: String,
+    comment: String,
+}
+
+/// Server found by network browsing.
+///
+/// See [`list_servers(..)`](struct.SmbClient.html#method.list_servers).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerInfo {
+    name: String,
+    comment: String,
+}
+
+/// Type of share.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShareKind {
+    /// Disk (file) share
+    Disk,
+    /// Printer share
+    Printer,
+    /// Communications device share
+    Comms,
+    /// IPC share (e.g. `IPC$`)
+    Ipc,
+}
+
+/// SMB protocol dialects which could be negotiated with server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SmbProtocol {
+    /// SMB1 (`NT1` dialect)
+    NT1,
+    /// SMB2
+    SMB2,
+    /// SMB3
+    SMB3,
+}
+
+/// SMB session encryption level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncryptionLevel {
+    /// Don't encrypt
+    None,
+    /// Encrypt if server supports it
+    Request,
+    /// Fail if server can't encrypt session
+    Require,
+}
+
+/// Windows share (deny) mode: what other opens of the same file are allowed
+/// while it's open.
+///
+/// See [`OpenOptions::share_mode`](struct.OpenOptions.html#method.share_mode).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShareMode {
+    /// Others may read and write (`libsmbclient` default)
+    DenyNone,
+    /// Others may write but not read
+    DenyRead,
+    /// Others may read but not write
+    DenyWrite,
+    /// File is opened exclusively
+    DenyAll,
 }
 
 // {{{2
@@ -123,386 +432,5993 @@ pub struct SmbClient<'a> {
 pub struct SmbFile<'a: 'b, 'b> {
     smbc: &'b SmbClient<'a>,
     fd: *mut SMBCFILE,
+    path: String,
+    /// Opened with `O_APPEND`, each write goes to end of file
+    append: bool,
 }
-// 1}}}
 
-/// Default (dummy) credential `WORKGROUP\guest` with empty password
-const DEF_CRED: (Cow<'static, str>, Cow<'static, str>, Cow<'static, str>) = (
-    Cow::Borrowed("WORKGROUP"),
-    Cow::Borrowed("guest"),
-    Cow::Borrowed(""),
-);
+/// Positional I/O on [`SmbFile`](struct.SmbFile.html),
+/// like `std::os::unix::fs::FileExt`.
+///
+/// `libsmbclient` has no `pread`/`pwrite`, so each call seeks to `offset`,
+/// does I/O and seeks back: file position observed by `Read`, `Write`
+/// and `Seek` isn't changed.
+///
+/// These calls don't run concurrently: `libsmbclient` context can't be used
+/// from several threads at once, so neither `SmbClient` nor `SmbFile` is `Sync`.
+/// To read regions of file in parallel open it in each thread through own
+/// [`try_clone`](struct.SmbClient.html#method.try_clone) of client, like
+/// [`download_parallel`](struct.SmbClient.html#method.download_parallel) does.
+///
+/// ```rust
+/// # fn main() {}
+/// #
+/// use smbc::FileExt;
+///
+/// fn read_header(client: &smbc::SmbClient, path: &str) -> smbc::Result<[u8; 16]> {
+///     let file = client.open(path)?;
+///     let mut header = [0u8; 16];
+///     file.read_exact_at(&mut header, 0)?;
+///     Ok(header)
+/// }
+/// ```
+pub trait FileExt {
+    /// Read bytes at `offset` returning number of bytes read.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
 
-// SmbClient {{{1
-impl<'a> SmbClient<'a> {
-    // {{{2
-    /// Creates new `SmbClient` given auth function.
+    /// Write bytes at `offset` returning number of bytes written.
     ///
-    /// `auth_fn` receives two callback parameters:
-    ///
-    /// * `server` -- server for which auth is requested
-    /// * `share` -- share for which auth is requested
-    ///
-    /// Should *return* tuple `(workgroup, username, password)` as a result.
-    pub fn new<F>(auth_fn: &'a F) -> Result<SmbClient<'a>>
-    where
-        F: for<'b> Fn(&'b str, &'b str) -> (Cow<'a, str>, Cow<'a, str>, Cow<'a, str>),
-    {
-        let mut smbc = SmbClient {
-            ctx: ptr::null_mut(),
-            auth_fn,
-        };
+    /// Files opened for appending are written at `offset` too.
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize>;
 
-        unsafe {
-            let ctx = result_from_ptr_mut(smbc_new_context())?;
+    /// Read exactly `buf.len()` bytes at `offset`,
+    /// fails with `io::ErrorKind::UnexpectedEof` if file is shorter.
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset) {
+                Ok(0) => break,
+                Ok(n) => {
+                    let tmp = buf;
+                    buf = &mut tmp[n..];
+                    offset += n as u64;
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if buf.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            ))
+        }
+    }
 
-            smbc_setOptionUserData(ctx, auth_fn as *const _ as *mut c_void);
-            smbc_setFunctionAuthDataWithContext(ctx, Some(Self::auth_wrapper::<F>));
+    /// Write whole `buf` at `offset`.
+    fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write_at(buf, offset) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write whole buffer",
+                    ))
+                }
+                Ok(n) => {
+                    buf = &buf[n..];
+                    offset += n as u64;
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
 
-            smbc_setOptionOneSharePerServer(ctx, SMBC_TRUE);
+/// Digest computed over content streamed by
+/// [`copy_with_digest(..)`](struct.SmbClient.html#method.copy_with_digest) and
+/// [`download_to_with_digest(..)`](struct.SmbClient.html#method.download_to_with_digest),
+/// like `std::hash::Hasher` with arbitrary output.
+///
+/// Wrap hash from crate of choice (e.g. SHA-256) to verify integrity
+/// without reading copy again. `DefaultHasher` implements it too,
+/// but it isn't cryptographic and its output isn't stable across Rust releases.
+pub trait Digest {
+    /// Computed digest, e.g. `[u8; 32]`.
+    type Output;
 
-            smbc_setOptionDebugToStderr(ctx, SMBC_TRUE);
-            //smbc_setDebug(ctx, 10);
+    /// Feed next chunk of content.
+    fn update(&mut self, data: &[u8]);
 
-            smbc.ctx = result_from_ptr_mut(smbc_init_context(ctx))?;
-        }
+    /// Digest of all content fed so far.
+    fn finalize(self) -> Self::Output;
+}
 
-        trace!(target: "smbc", "new smbclient");
-        Ok(smbc)
-    }
+/// Digest of transfers not computing it, `Option<&mut NoDigest>` is always `None`
+enum NoDigest {}
 
-    /// Auth wrapper passed to `SMBCCTX` to authenticate requests to SMB servers.
-    extern "C" fn auth_wrapper<F: 'a>(
-        ctx: *mut SMBCCTX,
-        srv: *const c_char,
-        shr: *const c_char,
-        wg: *mut c_char,
-        wglen: c_int,
-        un: *mut c_char,
-        unlen: c_int,
-        pw: *mut c_char,
-        pwlen: c_int,
-    ) -> ()
-    where
-        F: for<'b> Fn(&'b str, &'b str) -> (Cow<'a, str>, Cow<'a, str>, Cow<'a, str>),
-    {
-        unsafe {
-            let srv = cstr(srv);
-            let shr = cstr(shr);
-            trace!(target: "smbc", "authenticating on {}\\{}", &srv, &shr);
+/// Reader feeding everything read from `inner` to `digest`
+struct DigestRead<'d, R, D: 'd> {
+    inner: R,
+    digest: Option<&'d mut D>,
+}
 
-            let auth: &'a F = mem::transmute(smbc_getOptionUserData(ctx) as *const c_void);
-            let auth = panic::AssertUnwindSafe(auth);
-            let r = panic::catch_unwind(|| {
-                trace!(target: "smbc", "auth with {:?}\\{:?}", srv, shr);
-                auth(&srv, &shr)
-            });
-            let (workgroup, username, password) = r.unwrap_or(DEF_CRED);
-            trace!(target: "smbc", "cred: {}\\{} {}", &workgroup, &username, &password);
-            write_to_cstr(wg as *mut u8, wglen as usize, &workgroup);
-            write_to_cstr(un as *mut u8, unlen as usize, &username);
-            write_to_cstr(pw as *mut u8, pwlen as usize, &password);
-        }
-        ()
-    }
+/// Print job opened on printer share.
+///
+/// Data written to it is spooled by server and printed once job is
+/// finished. See [`open_print_job(..)`](struct.SmbClient.html#method.open_print_job).
+pub struct SmbPrintJob<'a: 'b, 'b> {
+    file: SmbFile<'a, 'b>,
+}
 
-    /// Opens [`SmbFile`](struct.SmbFile.html) defined by SMB `path` with `options`.
-    ///
-    /// See [OpenOptions](struct.OpenOptions.html).
-    pub fn open_with<'b, P: AsRef<str>>(
-        &'b self,
-        path: P,
-        options: OpenOptions,
-    ) -> Result<SmbFile<'a, 'b>> {
-        trace!(target: "smbc", "open_with {:?}", options);
+/// Job in printer queue.
+///
+/// See [`list_print_jobs(..)`](struct.SmbClient.html#method.list_print_jobs).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrintJobInfo {
+    id: u16,
+    priority: u16,
+    size: u64,
+    user: String,
+    name: String,
+    spooled: SystemTime,
+}
 
-        let open_fn = self.get_fn(smbc_getFunctionOpen)?;
+/// Position in directory listing returned by
+/// [`SmbDirectory::tell()`](struct.SmbDirectory.html#method.tell).
+///
+/// It's opaque and valid only for the same open directory:
+/// `libsmbclient` caches listing on open and position refers to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DirOffset(off_t);
 
-        let path = cstring(path)?;
-        trace!(target: "smbc", "opening {:?}", path);
+/// Open SMB directory.
+///
+/// It corresponds to `libsmbclient`'s `SMBCFILE *` returned by `opendir`,
+/// see [`open_dir(..)`](struct.SmbClient.html#method.open_dir).
+pub struct SmbDirectory<'a: 'b, 'b> {
+    smbc: &'b SmbClient<'a>,
+    fd: *mut SMBCFILE,
+}
 
-        let fd = result_from_ptr_mut(open_fn(
-            self.ctx,
-            path.as_ptr(),
-            options.to_flags()?,
-            options.mode,
-        ))?;
-        if (fd as i64) < 0 {
-            trace!(target: "smbc", "neg fd");
-        }
-        Ok(SmbFile { smbc: &self, fd })
-    }
+/// DOS attributes of file or directory.
+///
+/// See [`dos_attributes(..)`](struct.SmbClient.html#method.dos_attributes).
+///
+/// ```rust
+/// let attrs = smbc::DosAttributes::HIDDEN | smbc::DosAttributes::READONLY;
+/// assert!(attrs.contains(smbc::DosAttributes::HIDDEN));
+/// assert!(!attrs.contains(smbc::DosAttributes::ARCHIVE));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DosAttributes(u32);
 
-    /// Open read-only [`SmbFile`](struct.SmbFile.html) defined by SMB `path`.
-    ///
-    /// Alias for [`open_ro(..)`](struct.SmbClient.html#method.open_ro).
-    pub fn open<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbFile<'a, 'b>> {
-        self.open_ro(path)
-    }
+/// DOS attributes with timestamps of file or directory.
+///
+/// See [`dos_info(..)`](struct.SmbClient.html#method.dos_info).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DosInfo {
+    attributes: DosAttributes,
+    created: Option<SystemTime>,
+    accessed: Option<SystemTime>,
+    written: Option<SystemTime>,
+    changed: Option<SystemTime>,
+}
 
-    /// Open write-only [`SmbFile`](struct.SmbFile.html) defined by SMB `path`.
-    ///
-    /// If file doesn't exists it will be created.
-    /// If file exists it will be truncated.
-    ///
-    /// Alias for [`open_wo(..)`](struct.SmbClient.html#method.open_wo).
-    pub fn create<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbFile<'a, 'b>> {
-        self.open_wo(path)
-    }
+/// Iterator over entries of directory.
+///
+/// See [`read_dir(..)`](struct.SmbClient.html#method.read_dir).
+pub struct ReadDir<'a: 'b, 'b> {
+    dir: SmbDirectory<'a, 'b>,
+    path: String,
+}
 
-    /// Open read-only [`SmbFile`](struct.SmbFile.html) defined by SMB `path`.
-    ///
-    /// See [`open_with(..)`](struct.SmbClient.html#method.open_with).
-    pub fn open_ro<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbFile<'a, 'b>> {
-        self.open_with(path, OpenOptions::default())
-    }
+/// Iterator over entries of directory with their metadata.
+///
+/// See [`read_dir_plus(..)`](struct.SmbClient.html#method.read_dir_plus).
+pub struct ReadDirPlus<'a: 'b, 'b> {
+    inner: ReadDir<'a, 'b>,
+    readdirplus_fn: smbc_readdirplus_fn,
+    started: bool,
+}
 
-    /// Open write-only [`SmbFile`](struct.SmbFile.html) defined by SMB `path`.
-    ///
-    /// If file doesn't exists it will be created.
-    /// If file exists it will be truncated.
-    ///
-    /// See [`open_with(..)`](struct.SmbClient.html#method.open_with).
-    pub fn open_wo<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbFile<'a, 'b>> {
-        self.open_with(
-            path,
-            OpenOptions::default()
-                .read(false)
-                .write(true)
-                .create(true)
-                .truncate(true),
-        )
-    }
+/// Recursive iterator over directory tree yielding `(depth, entry, metadata)`.
+///
+/// See [`walk(..)`](struct.SmbClient.html#method.walk).
+pub struct Walk<'a: 'b, 'b> {
+    smbc: &'b SmbClient<'a>,
+    /// Directory to open on next step (root or directory yielded last)
+    pending: Option<String>,
+    stack: Vec<WalkLevel<'a, 'b>>,
+    max_depth: usize,
+    follow_links: bool,
+    sort_by_name: bool,
+    hidden_shares: bool,
+}
 
-    /// Open read-write [`SmbFile`](struct.SmbFile.html) defined by SMB `path`.
-    ///
-    /// If file doesn't exists it will be created.
-    ///
-    /// See [`open_with(..)`](struct.SmbClient.html#method.open_with).
-    pub fn open_rw<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbFile<'a, 'b>> {
-        self.open_with(
-            path,
-            OpenOptions::default().read(true).write(true).create(true),
-        )
-    }
+/// Callback of `SmbDirectory::notify` with payload of its panic
+type NotifyState<F> = (F, Option<Box<dyn Any + Send>>);
 
-    #[doc(hidden)]
-    /// Get metadata for file at `path`
-    pub fn metadata<P: AsRef<str>>(&self, path: P) -> Result<()> {
-        let _stat_fn = self.get_fn(smbc_getFunctionStat)?;
-        let _path = cstring(path)?;
-        unimplemented!();
-    }
+/// Directory being listed by `Walk`
+enum WalkLevel<'a: 'b, 'b> {
+    Dir(ReadDirPlus<'a, 'b>),
+    Sorted(vec::IntoIter<Result<(DirEntry, Metadata)>>),
+}
 
-    /// Create new directory at SMB `path`
-    pub fn create_dir<P: AsRef<str>>(&self, path: P) -> Result<()> {
-        let mkdir_fn = self.get_fn(smbc_getFunctionMkdir)?;
-        let path = cstring(path)?;
-        to_result_with_le(mkdir_fn(self.ctx, path.as_ptr(), 0o755))?;
-        Ok(())
-    }
+/// Totals of directory tree.
+///
+/// See [`dir_size(..)`](struct.SmbClient.html#method.dir_size).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DirSize {
+    bytes: u64,
+    files: u64,
+    dirs: u64,
+}
 
-    //    pub fn create_dir_all<P: AsRef<str>>(&self, path: P) -> Result<()> {
-    //        unimplemented!();
-    //    }
+/// Directory tree loaded into memory.
+///
+/// See [`read_tree(..)`](struct.SmbClient.html#method.read_tree).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeNode {
+    name: String,
+    is_dir: bool,
+    children: Vec<TreeNode>,
+    metadata: Metadata,
+}
 
-    /// Delete directory at SMB `path`.
-    ///
-    /// Directory should be empty to delete it.
-    pub fn remove_dir<P: AsRef<str>>(&self, path: P) -> Result<()> {
-        let rmdir_fn = self.get_fn(smbc_getFunctionRmdir)?;
-        let path = cstring(path)?;
-        to_result_with_le(rmdir_fn(self.ctx, path.as_ptr()))?;
-        Ok(())
-    }
+/// Outcome of directory tree transfer.
+///
+/// See [`download_tree(..)`](struct.SmbClient.html#method.download_tree) and
+/// [`upload_tree(..)`](struct.SmbClient.html#method.upload_tree).
+#[derive(Debug, Default)]
+pub struct TreeTransfer {
+    pub(crate) bytes: u64,
+    pub(crate) files: u64,
+    pub(crate) dirs: u64,
+    pub(crate) errors: Vec<Error>,
+}
 
-    fn get_fn<T>(
-        &self,
-        get_func: unsafe extern "C" fn(*mut SMBCCTX) -> Option<T>,
-    ) -> io::Result<T> {
-        unsafe { get_func(self.ctx).ok_or(io::Error::from_raw_os_error(libc::EINVAL as i32)) }
-    }
-} // 2}}}
+/// Progress of bulk transfer reported to `*_with_progress` callbacks
+/// after each chunk.
+///
+/// Callbacks of single file helpers receive progress of that file alone.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub(crate) path: String,
+    pub(crate) file_bytes: u64,
+    pub(crate) file_size: u64,
+    pub(crate) bytes: u64,
+    pub(crate) files: u64,
+    pub(crate) total_bytes: Option<u64>,
+    pub(crate) total_files: Option<u64>,
+}
+
+/// Entry of directory returned by [`ReadDir`](struct.ReadDir.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    path: String,
+    name: String,
+    kind: EntryType,
+    comment: String,
+}
+
+/// Type of directory entry.
+///
+/// Listing `smb://` gives workgroups, workgroup gives servers
+/// and server gives shares.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryType {
+    /// Workgroup (domain)
+    Workgroup,
+    /// Server
+    Server,
+    /// Share of given type
+    Share(ShareKind),
+    /// Directory
+    Dir,
+    /// Regular file
+    File,
+    /// Link (e.g. DFS link)
+    Link,
+}
+
+/// Set of changes [`watch`](struct.SmbDirectory.html#method.watch) reports.
+///
+/// Filters are combined with `|`:
+///
+/// ```rust
+/// let filter = smbc::NotifyFilter::FILE_NAME | smbc::NotifyFilter::LAST_WRITE;
+/// assert!(filter.contains(smbc::NotifyFilter::FILE_NAME));
+/// assert!(!filter.contains(smbc::NotifyFilter::SIZE));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NotifyFilter(u32);
+
+/// Kind of change reported by [`watch`](struct.SmbDirectory.html#method.watch).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChangeAction {
+    /// Entry was created
+    Added,
+    /// Entry was removed
+    Removed,
+    /// Entry was modified
+    Modified,
+    /// Entry was renamed, old name
+    RenamedFrom,
+    /// Entry was renamed, new name
+    RenamedTo,
+    /// Named stream was added
+    AddedStream,
+    /// Named stream was removed
+    RemovedStream,
+    /// Named stream was modified
+    ModifiedStream,
+}
+
+/// Change in watched directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangeEvent {
+    action: ChangeAction,
+    filename: String,
+}
+
+/// Blocking iterator over changes in watched directory.
+///
+/// See [`watcher(..)`](struct.SmbClient.html#method.watcher).
+pub struct Watcher<'a: 'b, 'b> {
+    dir: SmbDirectory<'a, 'b>,
+    recursive: bool,
+    filter: NotifyFilter,
+    pending: VecDeque<ChangeEvent>,
+    failed: bool,
+}
+
+/// Several SMB files read one after another as a single stream.
+///
+/// See [`concat_read(..)`](struct.SmbClient.html#method.concat_read).
+pub struct ConcatRead<'a: 'b, 'b> {
+    smbc: &'b SmbClient<'a>,
+    paths: Vec<String>,
+    next: usize,
+    current: Option<SmbFile<'a, 'b>>,
+}
+// 1}}}
+
+/// Check if error may go away if operation is retried later.
+///
+/// Sharing violations are reported by `libsmbclient` as `EBUSY`.
+fn is_transient(err: &Error) -> bool {
+    match *err {
+        Error::Io(ref err) => matches!(
+            err.raw_os_error(),
+            Some(libc::EBUSY) | Some(libc::EAGAIN) | Some(libc::ETIMEDOUT) | Some(libc::EINTR)
+        ),
+        _ => false,
+    }
+}
+
+/// Copy rest of `src` to `dst` through buffer of `buf_size` bytes
+fn stream_copy<R: Read, W: Write>(src: &mut R, dst: &mut W, buf_size: usize) -> Result<u64> {
+    stream_copy_with_progress(src, dst, buf_size, |_| {})
+}
+
+/// Same as `stream_copy` calling `progress` with bytes copied so far after each chunk
+fn stream_copy_with_progress<R, W, F>(
+    src: &mut R,
+    dst: &mut W,
+    buf_size: usize,
+    mut progress: F,
+) -> Result<u64>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(u64),
+{
+    let mut buf = vec![0u8; buf_size];
+    let mut copied = 0u64;
+    loop {
+        let bytes_read = match src.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        dst.write_all(&buf[..bytes_read])?;
+        copied += bytes_read as u64;
+        progress(copied);
+    }
+    Ok(copied)
+}
+
+/// Offset copying `src_len` bytes from `src_start` of `src` to partial copy `dst`
+/// of `dst_len` bytes could be resumed from.
+///
+/// It's `0` if `dst` is longer than source or its last `verify` bytes differ.
+fn resume_offset<S, D>(
+    src: &mut S,
+    src_start: u64,
+    src_len: u64,
+    dst: &mut D,
+    dst_len: u64,
+    verify: u64,
+) -> Result<u64>
+where
+    S: Read + Seek,
+    D: Read + Seek,
+{
+    if dst_len > src_len {
+        return Ok(0);
+    }
+    let tail = cmp::min(verify, dst_len);
+    if tail > 0 {
+        src.seek(SeekFrom::Start(src_start + dst_len - tail))?;
+        dst.seek(SeekFrom::Start(dst_len - tail))?;
+        if !same_content(&mut src.take(tail), &mut dst.take(tail))? {
+            trace!(target: "smbc", "last {} bytes differ, can't resume", tail);
+            return Ok(0);
+        }
+    }
+    Ok(dst_len)
+}
+
+/// Sleep while transfer started at `started` is ahead of `rate` bytes per second
+/// after `copied` bytes
+fn throttle(started: Instant, copied: u64, rate: u64) {
+    let expected = Duration::from_secs_f64(copied as f64 / rate as f64);
+    if let Some(ahead) = expected.checked_sub(started.elapsed()) {
+        thread::sleep(ahead);
+    }
+}
+
+/// Split `total` bytes into at most `streams` ranges `(start, len)`,
+/// no more than one per `chunk_size` bytes and always at least one (maybe empty).
+#[allow(clippy::manual_div_ceil)] // `div_ceil` requires Rust 1.73
+fn byte_ranges(total: u64, chunk_size: u64, streams: u64) -> Vec<(u64, u64)> {
+    let chunks = (total + chunk_size - 1) / chunk_size;
+    let streams = cmp::max(1, cmp::min(streams, chunks));
+    let range_len = cmp::max(1, (total + streams - 1) / streams);
+    let mut ranges = vec![(0, cmp::min(range_len, total))];
+    let mut start = range_len;
+    while start < total {
+        ranges.push((start, cmp::min(range_len, total - start)));
+        start += range_len;
+    }
+    ranges
+}
+
+/// Set access and modification times of local file at `path` (second precision)
+fn set_local_file_times(path: &Path, accessed: SystemTime, modified: SystemTime) -> Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let times = libc::utimbuf {
+        actime: to_timeval(accessed).tv_sec,
+        modtime: to_timeval(modified).tv_sec,
+    };
+    to_result_with_le(unsafe { libc::utime(c_path.as_ptr(), &times) })?;
+    Ok(())
+}
+
+/// Size of intermediate buffer for vectored I/O over buffers of `lens`,
+/// `None` if there's at most one non-empty buffer or the first one
+/// alone reaches `max` (so it's used directly)
+fn vectored_limit<I: Iterator<Item = usize>>(lens: I, max: usize) -> Option<usize> {
+    let mut lens = lens.filter(|&len| len > 0);
+    let first = lens.next()?;
+    if first >= max {
+        return None;
+    }
+    let rest: usize = lens.sum();
+    if rest == 0 {
+        return None;
+    }
+    Some(cmp::min(first.saturating_add(rest), max))
+}
+
+/// First non-empty buffer of `bufs` or empty one
+fn first_nonempty_mut<'s>(bufs: &'s mut [IoSliceMut]) -> &'s mut [u8] {
+    match bufs.iter_mut().find(|b| !b.is_empty()) {
+        Some(buf) => &mut buf[..],
+        None => &mut [],
+    }
+}
+
+/// Call xattr function `f` with buffer and its size until value fits.
+///
+/// Size is queried with empty buffer first, buffer is grown on `ERANGE`
+/// (value changed in between or query isn't supported). Returns buffer
+/// and value length reported by `f`.
+fn with_xattr_buf<F>(mut f: F) -> Result<(Vec<u8>, usize)>
+where
+    F: FnMut(*mut u8, usize) -> Result<c_int>,
+{
+    let is_erange = |err: &Error| match *err {
+        Error::Io(ref err) => err.raw_os_error() == Some(libc::ERANGE),
+        _ => false,
+    };
+
+    let mut size = match f(ptr::null_mut(), 0) {
+        Ok(size) if size > 0 => size as usize + 1,
+        Ok(_) => XATTR_BUF_SIZE,
+        Err(ref err) if is_erange(err) => XATTR_BUF_SIZE,
+        Err(err) => return Err(err),
+    };
+    for _ in 0..XATTR_MAX_RETRIES {
+        let mut buf = vec![0u8; size];
+        match f(buf.as_mut_ptr(), buf.len()) {
+            Ok(len) => return Ok((buf, len as usize)),
+            Err(ref err) if is_erange(err) => {
+                size *= 2;
+                trace!(target: "smbc", "xattr value truncated, retrying with {}", size);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Err(io::Error::from_raw_os_error(libc::ERANGE).into())
+}
+
+/// Name of entry to sort `Walk` level by, errors go first
+fn walk_entry_name(entry: &Result<(DirEntry, Metadata)>) -> Option<&str> {
+    entry.as_ref().ok().map(|entry| &entry.0.name[..])
+}
+
+/// Assemble tree under `root` from `(depth, node)` pairs in `walk` order,
+/// i.e. every node follows its parent. Fails if there are more
+/// than `max_nodes` of them.
+fn build_tree<I>(root: TreeNode, nodes: I, max_nodes: usize) -> Result<TreeNode>
+where
+    I: IntoIterator<Item = Result<(usize, TreeNode)>>,
+{
+    // `stack[depth]` is last node seen at `depth`, its children are complete
+    // once node of the same or lower depth shows up
+    let mut stack = vec![root];
+    for (count, node) in nodes.into_iter().enumerate() {
+        let (depth, node) = node?;
+        if count == max_nodes {
+            let msg = format!("directory tree has more than {} entries", max_nodes);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg).into());
+        }
+        while stack.len() > depth {
+            let done = stack.pop().expect("root is never popped");
+            stack
+                .last_mut()
+                .expect("root is never popped")
+                .children
+                .push(done);
+        }
+        stack.push(node);
+    }
+    while stack.len() > 1 {
+        let done = stack.pop().expect("root is never popped");
+        stack
+            .last_mut()
+            .expect("root is never popped")
+            .children
+            .push(done);
+    }
+    Ok(stack.pop().expect("root is never popped"))
+}
+
+/// Scheduler of `walk_parallel`: lists directories under `root` with `list`
+/// in thread per item of `contexts` and passes entries to `visit`.
+///
+/// Calling thread keeps stack of directories to list and hands them out
+/// so that each worker has at most one; listings come back through channel
+/// of the same size. On error workers are stopped once in-flight listings
+/// are received. Panic of `list` stops the walk too and is resumed
+/// on calling thread.
+fn walk_with_workers<C, L, V>(contexts: Vec<C>, root: String, list: L, mut visit: V) -> Result<()>
+where
+    C: Send + 'static,
+    L: Fn(&C, &str) -> Result<Vec<(DirEntry, Metadata)>> + Send + Sync + 'static,
+    V: FnMut(DirEntry),
+{
+    let capacity = contexts.len();
+    let (job_tx, job_rx) = mpsc::sync_channel::<String>(capacity);
+    let (done_tx, done_rx) = mpsc::sync_channel(capacity);
+    let job_rx = Arc::new(Mutex::new(job_rx));
+    let list = Arc::new(list);
+
+    let mut workers = Vec::new();
+    for context in contexts {
+        let (job_rx, done_tx, list) = (job_rx.clone(), done_tx.clone(), list.clone());
+        workers.push(thread::spawn(move || loop {
+            // lock is released as soon as job is taken
+            let dir = match job_rx.lock().expect("job queue poisoned").recv() {
+                Ok(dir) => dir,
+                Err(_) => break,
+            };
+            // job must be reported even if `list` panics, or walk waits for it forever
+            let listing = panic::catch_unwind(panic::AssertUnwindSafe(|| list(&context, &dir)));
+            let panicked = listing.is_err();
+            if done_tx.send(listing.map(|res| at_path(&dir, res))).is_err() || panicked {
+                break;
+            }
+        }));
+    }
+    drop(done_tx);
+
+    let mut pending = vec![root];
+    let mut in_flight = 0;
+    let mut res = Ok(());
+    let mut panicked = None;
+    loop {
+        while res.is_ok() && panicked.is_none() && in_flight < capacity {
+            match pending.pop() {
+                Some(dir) => {
+                    // can't block, there are fewer jobs than workers
+                    job_tx.send(dir).expect("walk workers exited early");
+                    in_flight += 1;
+                }
+                None => break,
+            }
+        }
+        if in_flight == 0 {
+            break;
+        }
+        let listing = match done_rx.recv() {
+            Ok(listing) => listing,
+            // every worker panicked, so queued jobs won't be taken
+            Err(_) => break,
+        };
+        in_flight -= 1;
+        let entries = match listing {
+            Ok(Ok(entries)) => entries,
+            Ok(Err(err)) => {
+                if res.is_ok() {
+                    res = Err(err);
+                }
+                continue;
+            }
+            Err(payload) => {
+                if panicked.is_none() {
+                    panicked = Some(payload);
+                }
+                continue;
+            }
+        };
+        if res.is_err() || panicked.is_some() {
+            continue;
+        }
+        for (entry, metadata) in entries {
+            let descend = match entry.kind {
+                EntryType::Share(ShareKind::Disk) => !entry.name.ends_with('$'),
+                EntryType::Dir => metadata.file_type() != FileType::Symlink,
+                _ => false,
+            };
+            if descend {
+                pending.push(entry.path.clone());
+            }
+            visit(entry);
+        }
+    }
+
+    drop(job_tx);
+    for worker in workers {
+        worker
+            .join()
+            .unwrap_or_else(|err| panic::resume_unwind(err));
+    }
+    if let Some(payload) = panicked {
+        panic::resume_unwind(payload);
+    }
+    res
+}
+
+/// Descriptor `overwrite_preserving_acl` writes back: ACL of `sd` without
+/// owner and group or nothing if it couldn't be read
+fn acl_to_restore(path: &str, sd: Result<SecurityDescriptor>) -> Option<SecurityDescriptor> {
+    match sd {
+        Ok(mut sd) => {
+            sd.set_owner(None);
+            sd.set_group(None);
+            Some(sd)
+        }
+        Err(err) => {
+            trace!(target: "smbc", "no acl to restore for {}: {}", path, err);
+            None
+        }
+    }
+}
+
+/// Local path of entry `name` of directory downloaded to `dir`.
+///
+/// Name comes from server, so it must be single plain component
+/// to not escape `dir`.
+fn local_entry_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !name.contains('/') => Ok(dir.join(name)),
+        _ => {
+            let msg = format!("unsafe file name {:?}", name);
+            Err(io::Error::new(io::ErrorKind::InvalidData, msg).into())
+        }
+    }
+}
+
+/// URL of server or workgroup given by `name` or `smb://name` URL
+fn browse_url(name: &str) -> String {
+    const SCHEME: &str = "smb://";
+    let name = match name.get(..SCHEME.len()) {
+        Some(scheme) if scheme.eq_ignore_ascii_case(SCHEME) => &name[SCHEME.len()..],
+        _ => name,
+    };
+    let mut url = SCHEME.to_owned();
+    percent_encode_to(name.trim_end_matches('/'), &mut url);
+    url.push('/');
+    url
+}
+
+/// URL of entry `name` of directory at `dir` URL
+fn entry_path(dir: &str, name: &str) -> String {
+    let mut path = dir.to_owned();
+    if !path.ends_with('/') {
+        path.push('/');
+    }
+    percent_encode_to(name, &mut path);
+    path
+}
+
+/// Attach `path` to error of `res`
+fn at_path<T>(path: &str, res: Result<T>) -> Result<T> {
+    res.map_err(|err| Error::Path(path.to_owned(), Box::new(err)))
+}
+
+/// Attach local `path` to error of `res`
+fn at_local_path<T>(path: &Path, res: io::Result<T>) -> Result<T> {
+    res.map_err(|err| Error::Path(path.display().to_string(), Box::new(err.into())))
+}
+
+/// Auth function for anonymous access returning empty workgroup,
+/// username and password.
+///
+/// Could be passed to [`SmbClientBuilder::build(..)`](struct.SmbClientBuilder.html#method.build),
+/// see also [`SmbClient::new_anonymous()`](struct.SmbClient.html#method.new_anonymous).
+pub fn anonymous(
+    _server: &str,
+    _share: &str,
+) -> (Cow<'static, str>, Cow<'static, str>, Cow<'static, str>) {
+    (Cow::Borrowed(""), Cow::Borrowed(""), Cow::Borrowed(""))
+}
+
+thread_local! {
+    /// Jobs collected by `collect_print_job`, its callback has no user data pointer
+    #[allow(clippy::missing_const_for_thread_local)] // const initializer requires Rust 1.59
+    static PRINT_JOBS: RefCell<Vec<PrintJobInfo>> = RefCell::new(Vec::new());
+}
+
+/// Print job listing callback passed to `libsmbclient`.
+#[allow(clippy::unnecessary_cast)] // `size_t` width differs across platforms
+extern "C" fn collect_print_job(info: *mut print_job_info) {
+    if info.is_null() {
+        return;
+    }
+    let info = unsafe { &*info };
+    let job = PrintJobInfo {
+        id: info.id,
+        priority: info.priority,
+        size: info.size as u64,
+        user: unsafe { cstr(info.user.as_ptr()) }.into_owned(),
+        name: unsafe { cstr(info.name.as_ptr()) }.into_owned(),
+        spooled: to_system_time(info.t, 0),
+    };
+    trace!(target: "smbc", "print job {:?}", job);
+    PRINT_JOBS.with(|jobs| jobs.borrow_mut().push(job));
+}
+
+/// Splice progress callback passed to `libsmbclient`, never aborts.
+extern "C" fn splice_progress(_n: off_t, _private: *mut c_void) -> c_int {
+    1
+}
+
+/// Debug callback passed to `libsmbclient` by `SmbClientBuilder::debug_to_log`.
+extern "C" fn log_native(_private_ptr: *mut c_void, level: c_int, msg: *const c_char) {
+    if msg.is_null() {
+        return;
+    }
+    let msg = unsafe { cstr(msg) };
+    let msg = msg.trim_end();
+    if level <= 3 {
+        debug!(target: "smbc::native", "{}", msg);
+    } else {
+        trace!(target: "smbc::native", "{}", msg);
+    }
+}
+
+/// Default workgroup of fallback credential
+const DEF_WORKGROUP: &str = "WORKGROUP";
+/// Username of fallback credential (with empty password)
+const DEF_USERNAME: &str = "guest";
+/// Username given to server when auth function fails,
+/// `<` and `>` aren't allowed in account names so it can't log in
+const FAILED_AUTH_USERNAME: &str = "<auth failed>";
+
+// SmbClient {{{1
+impl<'a> SmbClient<'a> {
+    // {{{2
+    /// Creates new `SmbClient` given auth function.
+    ///
+    /// `auth_fn` receives two callback parameters:
+    ///
+    /// * `server` -- server for which auth is requested
+    /// * `share` -- share for which auth is requested
+    ///
+    /// Should *return* tuple `(workgroup, username, password)` as a result.
+    ///
+    /// `auth_fn` should be `Sync` since client may be moved to other thread.
+    ///
+    /// See [`SmbClientBuilder`](struct.SmbClientBuilder.html) to configure client.
+    pub fn new<F>(auth_fn: &'a F) -> Result<SmbClient<'a>>
+    where
+        F: for<'b> Fn(&'b str, &'b str) -> (Cow<'a, str>, Cow<'a, str>, Cow<'a, str>) + Sync,
+    {
+        SmbClientBuilder::default().build(auth_fn)
+    }
+
+    /// Creates new `SmbClient` for anonymous access.
+    ///
+    /// See [`anonymous`](fn.anonymous.html).
+    pub fn new_anonymous() -> Result<SmbClient<'static>> {
+        SmbClient::new(&anonymous)
+    }
+
+    /// Creates new `SmbClient` given auth function which may fail.
+    ///
+    /// If `auth_fn` returns `Err` operation which needed authentication fails
+    /// with `Error::AuthFailed` instead of trying guest access.
+    ///
+    /// See [`SmbClientBuilder::build_with_fallible_auth(..)`](struct.SmbClientBuilder.html#method.build_with_fallible_auth).
+    pub fn new_with_fallible_auth<F, E>(auth_fn: &'a F) -> Result<SmbClient<'a>>
+    where
+        F: for<'b> Fn(
+                &'b str,
+                &'b str,
+            )
+                -> ::std::result::Result<(Cow<'a, str>, Cow<'a, str>, Cow<'a, str>), E>
+            + Sync,
+        E: fmt::Display,
+    {
+        SmbClientBuilder::default().build_with_fallible_auth(auth_fn)
+    }
+
+    /// Auth wrapper passed to `SMBCCTX` to authenticate requests to SMB servers.
+    extern "C" fn auth_wrapper(
+        ctx: *mut SMBCCTX,
+        srv: *const c_char,
+        shr: *const c_char,
+        wg: *mut c_char,
+        wglen: c_int,
+        un: *mut c_char,
+        unlen: c_int,
+        pw: *mut c_char,
+        pwlen: c_int,
+    ) {
+        unsafe {
+            let srv = cstr(srv);
+            let shr = cstr(shr);
+            trace!(target: "smbc", "authenticating on {}\\{}", &srv, &shr);
+
+            let auth: &AuthData<'a> = &*(smbc_getOptionUserData(ctx) as *const AuthData);
+            let r = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                trace!(target: "smbc", "auth with {:?}\\{:?}", srv, shr);
+                (auth.auth_fn)(&srv, &shr)
+            }));
+            let (workgroup, username, password) = match r {
+                Ok(Ok(cred)) => cred,
+                Ok(Err(msg)) => {
+                    trace!(target: "smbc", "auth failed: {}", msg);
+                    if let Ok(mut failure) = auth.failure.lock() {
+                        *failure = Some(msg);
+                    }
+                    // empty username would log in anonymously
+                    (
+                        Cow::Borrowed(""),
+                        Cow::Borrowed(FAILED_AUTH_USERNAME),
+                        Cow::Borrowed(""),
+                    )
+                }
+                Err(_) => (
+                    Cow::Owned(auth.default_workgroup.clone()),
+                    Cow::Borrowed(DEF_USERNAME),
+                    Cow::Borrowed(""),
+                ),
+            };
+            trace!(target: "smbc", "cred: {}\\{} {}", &workgroup, &username, &password);
+            write_to_cstr(wg as *mut u8, wglen as usize, &workgroup);
+            write_to_cstr(un as *mut u8, unlen as usize, &username);
+            write_to_cstr(pw as *mut u8, pwlen as usize, &password);
+        }
+    }
+
+    /// Converts result of native call returning `Error::AuthFailed`
+    /// if auth function failed while call was made.
+    fn check<T>(&self, res: io::Result<T>) -> Result<T> {
+        match self.take_auth_failure() {
+            Some(msg) => Err(Error::AuthFailed(msg)),
+            None => res.map_err(Error::from_native),
+        }
+    }
+
+    /// Like `check` for calls reporting `io::Error`
+    /// (auth failure becomes `PermissionDenied` with its message).
+    fn check_io<T>(&self, res: io::Result<T>) -> io::Result<T> {
+        match self.take_auth_failure() {
+            Some(msg) => Err(io::Error::new(io::ErrorKind::PermissionDenied, msg)),
+            None => res,
+        }
+    }
+
+    /// Takes message of auth function failure recorded by `auth_wrapper`.
+    fn take_auth_failure(&self) -> Option<String> {
+        match self.auth.failure.lock() {
+            Ok(mut failure) => failure.take(),
+            Err(_) => None,
+        }
+    }
+
+    /// Opens [`SmbFile`](struct.SmbFile.html) defined by SMB `path` with `options`.
+    ///
+    /// Like all methods taking SMB paths it fails with `Error::InvalidUrl`
+    /// if `path` doesn't start with `smb://`, repeated slashes
+    /// in path part (e.g. `smb://host/share//file`) are collapsed.
+    /// Incompatible `options` are rejected with `Error::InvalidOpenOptions`
+    /// before anything is sent to server.
+    ///
+    /// See [OpenOptions](struct.OpenOptions.html).
+    pub fn open_with<'b, P: AsRef<str>>(
+        &'b self,
+        path: P,
+        options: OpenOptions,
+    ) -> Result<SmbFile<'a, 'b>> {
+        trace!(target: "smbc", "open_with {:?}", options);
+
+        let open_fn = self.get_fn(smbc_getFunctionOpen)?;
+
+        let path = path.as_ref();
+        let c_path = smb_cstring(path)?;
+        trace!(target: "smbc", "opening {:?}", c_path);
+
+        let flags = options.to_flags()?;
+        let open = || result_from_handle(open_fn(self.ctx, c_path.as_ptr(), flags, options.mode));
+        let fd = match options.share_mode {
+            // share mode is context option, `SmbClient` isn't used concurrently
+            // so it's safe to change it for one open
+            Some(mode) => unsafe {
+                let default = smbc_getOptionOpenShareMode(self.ctx);
+                smbc_setOptionOpenShareMode(self.ctx, mode.to_native());
+                let fd = open();
+                smbc_setOptionOpenShareMode(self.ctx, default);
+                fd
+            },
+            None => open(),
+        };
+        // file is built before check so it's closed if auth failed
+        self.check(fd.map(|fd| SmbFile {
+            smbc: self,
+            fd,
+            path: path.to_owned(),
+            append: flags & libc::O_APPEND != 0,
+        }))
+    }
+
+    /// Open read-only [`SmbFile`](struct.SmbFile.html) defined by SMB `path`.
+    ///
+    /// Alias for [`open_ro(..)`](struct.SmbClient.html#method.open_ro).
+    pub fn open<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbFile<'a, 'b>> {
+        self.open_ro(path)
+    }
+
+    /// Open write-only [`SmbFile`](struct.SmbFile.html) defined by SMB `path`.
+    ///
+    /// If file doesn't exists it will be created.
+    /// If file exists it will be truncated.
+    ///
+    /// Alias for [`open_wo(..)`](struct.SmbClient.html#method.open_wo).
+    pub fn create<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbFile<'a, 'b>> {
+        self.open_wo(path)
+    }
+
+    /// Open read-only [`SmbFile`](struct.SmbFile.html) defined by SMB `path`.
+    ///
+    /// See [`open_with(..)`](struct.SmbClient.html#method.open_with).
+    pub fn open_ro<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbFile<'a, 'b>> {
+        self.open_with(path, OpenOptions::default())
+    }
+
+    /// Open write-only [`SmbFile`](struct.SmbFile.html) defined by SMB `path`.
+    ///
+    /// If file doesn't exists it will be created.
+    /// If file exists it will be truncated.
+    ///
+    /// See [`open_with(..)`](struct.SmbClient.html#method.open_with).
+    pub fn open_wo<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbFile<'a, 'b>> {
+        self.open_with(
+            path,
+            OpenOptions::default()
+                .read(false)
+                .write(true)
+                .create(true)
+                .truncate(true),
+        )
+    }
+
+    /// Open read-write [`SmbFile`](struct.SmbFile.html) defined by SMB `path`.
+    ///
+    /// If file doesn't exists it will be created.
+    ///
+    /// See [`open_with(..)`](struct.SmbClient.html#method.open_with).
+    pub fn open_rw<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbFile<'a, 'b>> {
+        self.open_with(
+            path,
+            OpenOptions::default().read(true).write(true).create(true),
+        )
+    }
+
+    /// Open write-only [`SmbFile`](struct.SmbFile.html) defined by SMB `path`
+    /// for appending, like `O_WRONLY | O_APPEND | O_CREAT`.
+    ///
+    /// If file doesn't exists it will be created.
+    /// Each write goes to the current end of file even if other client
+    /// appended to it after this one opened it.
+    ///
+    /// ```rust
+    /// # use std::io::Write;
+    /// # fn main() {}
+    /// #
+    /// fn log_line(client: &smbc::SmbClient, line: &str) -> smbc::Result<()> {
+    ///     let mut log = client.open_append("smb://server/logs/app.log")?;
+    ///     writeln!(log, "{}", line)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// See [`open_with(..)`](struct.SmbClient.html#method.open_with).
+    pub fn open_append<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbFile<'a, 'b>> {
+        self.open_with(
+            path,
+            OpenOptions::default()
+                .read(false)
+                .write(true)
+                .append(true)
+                .create(true),
+        )
+    }
+
+    /// Opens [`SmbFile`](struct.SmbFile.html) defined by SMB `path` with `options`
+    /// retrying on transient errors (sharing violation, busy or timed out server).
+    ///
+    /// At most `max_attempts` opens are made, delay between them starts
+    /// with `base_delay` and doubles after each failed attempt.
+    /// Other errors are returned immediately.
+    ///
+    /// See [`open_with(..)`](struct.SmbClient.html#method.open_with).
+    pub fn open_with_retry<'b, P: AsRef<str>>(
+        &'b self,
+        path: P,
+        options: OpenOptions,
+        max_attempts: u32,
+        base_delay: Duration,
+    ) -> Result<SmbFile<'a, 'b>> {
+        let path = path.as_ref();
+        retry_with_backoff(max_attempts, base_delay, is_transient, || {
+            self.open_with(path, options)
+        })
+    }
+
+    /// Presents files at SMB `paths` as a single continuous stream.
+    ///
+    /// Files are opened read-only one by one when previous one is exhausted,
+    /// so failure to open some part (e.g. missing one) is returned
+    /// from `read` when stream reaches it.
+    pub fn concat_read<'b>(&'b self, paths: &[String]) -> ConcatRead<'a, 'b> {
+        ConcatRead {
+            smbc: self,
+            paths: paths.to_vec(),
+            next: 0,
+            current: None,
+        }
+    }
+
+    /// Size of single read or write done by bulk helpers, see
+    /// [`SmbClientBuilder::io_chunk_size`](struct.SmbClientBuilder.html#method.io_chunk_size).
+    pub fn io_chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Creates new independent client with the same options and auth function.
+    ///
+    /// Clone has its own `libsmbclient` context and connections, so it
+    /// could be moved to other thread and used concurrently with this one.
+    pub fn try_clone(&self) -> Result<SmbClient<'a>> {
+        trace!(target: "smbc", "cloning smbclient");
+        self.config
+            .clone()
+            .build_with_auth(self.auth.auth_fn.clone(), self.auth.fallible)
+    }
+
+    /// Get metadata for file or directory at SMB `path`.
+    pub fn metadata<P: AsRef<str>>(&self, path: P) -> Result<Metadata> {
+        self.stat(path).map(Metadata::from_stat)
+    }
+
+    /// Read whole file at SMB `path`, like `std::fs::read`.
+    ///
+    /// Buffer is presized to file size reported by server.
+    pub fn read<P: AsRef<str>>(&self, path: P) -> Result<Vec<u8>> {
+        let mut file = self.open(path)?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Write `contents` to file at SMB `path`, like `std::fs::write`.
+    ///
+    /// File is created if it doesn't exist and truncated otherwise.
+    pub fn write<P: AsRef<str>>(&self, path: P, contents: &[u8]) -> Result<()> {
+        let mut file = self.create(path)?;
+        file.write_all(contents)?;
+        Ok(())
+    }
+
+    /// Replace content of file at SMB `path` with everything read from `reader`
+    /// so other clients never see it partially written.
+    ///
+    /// Content is written to uniquely named temporary file in the same directory
+    /// which is renamed over `path` when it's completely written and closed.
+    /// On failure temporary file is removed and `path` is left intact.
+    ///
+    /// If server refuses to rename over existing file `libsmbclient` removes it
+    /// first, so there may be a short window when `path` doesn't exist.
+    /// Attributes and ACL of replaced file aren't kept.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn save_config(client: &smbc::SmbClient, config: &str) -> smbc::Result<()> {
+    ///     client.write_atomic("smb://server/share/app.conf", &mut config.as_bytes())?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_atomic<P: AsRef<str>, R: Read>(&self, path: P, reader: &mut R) -> Result<u64> {
+        let path = path.as_ref();
+        let trimmed = path.trim_end_matches('/');
+        let (dir, name) = trimmed.split_at(trimmed.rfind('/').map_or(0, |idx| idx + 1));
+
+        let options = OpenOptions::default()
+            .read(false)
+            .write(true)
+            .create(true)
+            .exclusive(true);
+        let mut attempt = 1;
+        let (temp, mut file) = loop {
+            let counter = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+            let temp = format!("{}.{}.{}-{}.tmp", dir, name, process::id(), counter);
+            match self.open_with(&temp, options) {
+                Ok(file) => break (temp, file),
+                Err(ref err) if err.is_already_exists() && attempt < TEMP_MAX_ATTEMPTS => {
+                    attempt += 1
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        trace!(target: "smbc", "writing {} through {}", path, temp);
+
+        let res = stream_copy(reader, &mut file, self.chunk_size)
+            .and_then(|written| file.close().map(|_| written))
+            .and_then(|written| self.rename(&temp, path).map(|_| written));
+        if res.is_err() {
+            if let Err(err) = self.remove_file(&temp) {
+                trace!(target: "smbc", "can't remove {}: {}", temp, err);
+            }
+        }
+        res
+    }
+
+    /// Copy file at SMB path `from` to SMB path `to` returning number of bytes copied.
+    ///
+    /// Destination is created or truncated. Server-side copy
+    /// (see [`splice(..)`](struct.SmbClient.html#method.splice)) is tried first,
+    /// if server can't do it content is streamed through client
+    /// in chunks of [`io_chunk_size`](struct.SmbClientBuilder.html#method.io_chunk_size).
+    /// Then source file mode is applied to destination if server allows it.
+    pub fn copy<P: AsRef<str>, Q: AsRef<str>>(&self, from: P, to: Q) -> Result<u64> {
+        self.copy_to(from, self, to)
+    }
+
+    /// Same as [`copy(..)`](struct.SmbClient.html#method.copy) calling `progress`
+    /// after each chunk (once for server-side copy).
+    pub fn copy_with_progress<P, Q, F>(&self, from: P, to: Q, progress: F) -> Result<u64>
+    where
+        P: AsRef<str>,
+        Q: AsRef<str>,
+        F: FnMut(&Progress),
+    {
+        self.copy_to_with_progress(from, self, to, progress)
+    }
+
+    /// Copy file at SMB path `from` to SMB path `to` opened by `dest` client
+    /// (e.g. on other server or with other credentials).
+    ///
+    /// Server-side copy is tried only if `dest` is this client.
+    ///
+    /// See [`copy(..)`](struct.SmbClient.html#method.copy).
+    pub fn copy_to<P: AsRef<str>, Q: AsRef<str>>(
+        &self,
+        from: P,
+        dest: &SmbClient,
+        to: Q,
+    ) -> Result<u64> {
+        self.copy_to_with_progress(from, dest, to, |_| {})
+    }
+
+    /// Same as [`copy_to(..)`](struct.SmbClient.html#method.copy_to) calling `progress`
+    /// after each chunk (once for server-side copy).
+    pub fn copy_to_with_progress<P, Q, F>(
+        &self,
+        from: P,
+        dest: &SmbClient,
+        to: Q,
+        progress: F,
+    ) -> Result<u64>
+    where
+        P: AsRef<str>,
+        Q: AsRef<str>,
+        F: FnMut(&Progress),
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        self.copy_impl(from, dest, to, None::<&mut NoDigest>, progress)
+    }
+
+    /// Same as [`copy(..)`](struct.SmbClient.html#method.copy) computing `digest`
+    /// of copied content, returns number of bytes copied and the digest.
+    ///
+    /// Content has to go through client, so server-side copy isn't tried.
+    pub fn copy_with_digest<P, Q, D>(
+        &self,
+        from: P,
+        to: Q,
+        mut digest: D,
+    ) -> Result<(u64, D::Output)>
+    where
+        P: AsRef<str>,
+        Q: AsRef<str>,
+        D: Digest,
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let copied = self.copy_impl(from, self, to, Some(&mut digest), |_| {})?;
+        Ok((copied, digest.finalize()))
+    }
+
+    /// Copy with digest computed over streamed content,
+    /// server-side copy is tried only without it
+    fn copy_impl<D, F>(
+        &self,
+        from: &str,
+        dest: &SmbClient,
+        to: &str,
+        digest: Option<&mut D>,
+        mut progress: F,
+    ) -> Result<u64>
+    where
+        D: Digest,
+        F: FnMut(&Progress),
+    {
+        trace!(target: "smbc", "copying {} to {}", from, to);
+        let st = self.stat(from)?;
+        let mode = st.st_mode;
+
+        let copied = {
+            let mut src = self.open(from)?;
+            let mut dst = dest.create(to)?;
+            let size = st.st_size as u64;
+            let spliced = if self.ctx == dest.ctx && digest.is_none() {
+                match self.splice(&mut src, &mut dst, size) {
+                    Ok(copied) if copied == size => Some(copied),
+                    res => {
+                        trace!(target: "smbc", "splice failed, streaming: {:?}", res);
+                        src.seek(SeekFrom::Start(0))?;
+                        dst.seek(SeekFrom::Start(0))?;
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            match spliced {
+                Some(copied) => {
+                    progress(&Progress::single(from, copied, size));
+                    copied
+                }
+                None => {
+                    let mut src = DigestRead {
+                        inner: &mut src,
+                        digest,
+                    };
+                    stream_copy_with_progress(&mut src, &mut dst, self.chunk_size, |n| {
+                        progress(&Progress::single(from, n, size))
+                    })?
+                }
+            }
+        };
+
+        if let Err(err) = dest.chmod(to, mode & 0o7777) {
+            trace!(target: "smbc", "can't preserve mode of {}: {}", to, err);
+        }
+        Ok(copied)
+    }
+
+    /// Download file at SMB path `from` to local file `to` returning number of bytes copied.
+    ///
+    /// Local file is created or truncated, content is streamed in chunks of
+    /// [`io_chunk_size`](struct.SmbClientBuilder.html#method.io_chunk_size).
+    ///
+    /// See [`TransferOptions`](struct.TransferOptions.html).
+    pub fn download_to<P: AsRef<str>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+        options: TransferOptions,
+    ) -> Result<u64> {
+        self.download_to_with_progress(from, to, options, |_| {})
+    }
+
+    /// Same as [`download_to(..)`](struct.SmbClient.html#method.download_to)
+    /// calling `progress` after each chunk.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn fetch(client: &smbc::SmbClient) -> smbc::Result<()> {
+    ///     let options = smbc::TransferOptions::default().preserve_mtime(true);
+    ///     client.download_to_with_progress(
+    ///         "smb://server/share/backup.tar",
+    ///         "/tmp/backup.tar",
+    ///         options,
+    ///         |p| eprint!("\r{}/{} bytes", p.file_bytes(), p.file_size()),
+    ///     )?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn download_to_with_progress<P, Q, F>(
+        &self,
+        from: P,
+        to: Q,
+        options: TransferOptions,
+        progress: F,
+    ) -> Result<u64>
+    where
+        P: AsRef<str>,
+        Q: AsRef<Path>,
+        F: FnMut(&Progress),
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        self.download_impl(from, to, options, None::<&mut NoDigest>, progress)
+    }
+
+    /// Same as [`download_to(..)`](struct.SmbClient.html#method.download_to)
+    /// computing `digest` of downloaded content, returns number of bytes
+    /// copied and the digest.
+    ///
+    /// When [`resume`](struct.TransferOptions.html#method.resume)d only
+    /// bytes transferred now go to `digest`.
+    ///
+    /// ```rust
+    /// # use std::collections::hash_map::DefaultHasher;
+    /// # fn main() {}
+    /// #
+    /// fn fetch(client: &smbc::SmbClient, expected: u64) -> smbc::Result<bool> {
+    ///     let options = smbc::TransferOptions::default();
+    ///     let (_, digest) = client.download_to_with_digest(
+    ///         "smb://server/share/backup.tar",
+    ///         "/tmp/backup.tar",
+    ///         options,
+    ///         DefaultHasher::new(),
+    ///     )?;
+    ///     Ok(digest == expected)
+    /// }
+    /// ```
+    pub fn download_to_with_digest<P, Q, D>(
+        &self,
+        from: P,
+        to: Q,
+        options: TransferOptions,
+        mut digest: D,
+    ) -> Result<(u64, D::Output)>
+    where
+        P: AsRef<str>,
+        Q: AsRef<Path>,
+        D: Digest,
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let copied = self.download_impl(from, to, options, Some(&mut digest), |_| {})?;
+        Ok((copied, digest.finalize()))
+    }
+
+    /// Download with digest computed over transferred content
+    fn download_impl<D, F>(
+        &self,
+        from: &str,
+        to: &Path,
+        options: TransferOptions,
+        digest: Option<&mut D>,
+        mut progress: F,
+    ) -> Result<u64>
+    where
+        D: Digest,
+        F: FnMut(&Progress),
+    {
+        trace!(target: "smbc", "downloading {} to {:?}", from, to);
+        let mut src = self.open(from)?;
+        let metadata = src.metadata()?;
+
+        if options.create_parents {
+            if let Some(parent) = to.parent() {
+                at_local_path(parent, fs::create_dir_all(parent))?;
+            }
+        }
+        let total = metadata.len();
+        let (mut dst, offset) = if options.resume {
+            let dst = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(to);
+            let mut dst = at_local_path(to, dst)?;
+            let len = dst.metadata()?.len();
+            let offset = resume_offset(&mut src, 0, total, &mut dst, len, options.verify_tail)?;
+            trace!(target: "smbc", "resuming download of {} at {}", from, offset);
+            dst.set_len(offset)?;
+            src.seek(SeekFrom::Start(offset))?;
+            dst.seek(SeekFrom::Start(offset))?;
+            (dst, offset)
+        } else {
+            (at_local_path(to, fs::File::create(to))?, 0)
+        };
+        let mut src = DigestRead {
+            inner: &mut src,
+            digest,
+        };
+        let copied = self.transfer(&mut src, &mut dst, options, |n| {
+            progress(&Progress::single(from, offset + n, total))
+        })?;
+        drop(dst);
+
+        if options.preserve_mode {
+            #[allow(clippy::unnecessary_cast)] // `mode_t` type differs across platforms
+            let mode = (metadata.permissions().mode() & 0o7777) as u32;
+            fs::set_permissions(to, fs::Permissions::from_mode(mode))?;
+        }
+        if options.preserve_mtime {
+            set_local_file_times(to, metadata.accessed(), metadata.modified())?;
+        }
+        Ok(copied)
+    }
+
+    /// Upload local file `from` to SMB path `to` returning number of bytes copied.
+    ///
+    /// Remote file is created or truncated, content is streamed in chunks of
+    /// [`io_chunk_size`](struct.SmbClientBuilder.html#method.io_chunk_size).
+    ///
+    /// See [`TransferOptions`](struct.TransferOptions.html).
+    pub fn upload_from<P: AsRef<Path>, Q: AsRef<str>>(
+        &self,
+        from: P,
+        to: Q,
+        options: TransferOptions,
+    ) -> Result<u64> {
+        self.upload_from_with_progress(from, to, options, |_| {})
+    }
+
+    /// Same as [`upload_from(..)`](struct.SmbClient.html#method.upload_from)
+    /// calling `progress` after each chunk.
+    pub fn upload_from_with_progress<P, Q, F>(
+        &self,
+        from: P,
+        to: Q,
+        options: TransferOptions,
+        progress: F,
+    ) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<str>,
+        F: FnMut(&Progress),
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let mut src = at_local_path(from, fs::File::open(from))?;
+        let metadata = src.metadata()?;
+        let copied = self.upload_from_reader_with_progress(&mut src, to, options, progress)?;
+
+        if options.preserve_mode {
+            self.chmod(to, metadata.permissions().mode() as mode_t & 0o7777)?;
+        }
+        if options.preserve_mtime {
+            self.set_file_times(to, metadata.accessed()?, metadata.modified()?)?;
+        }
+        Ok(copied)
+    }
+
+    /// Upload everything from current position of `reader` to SMB path `to`
+    /// returning number of bytes copied.
+    ///
+    /// `reader` has no mode or times to preserve, so only
+    /// [`create_parents`](struct.TransferOptions.html#method.create_parents)
+    /// option is used.
+    ///
+    /// See [`upload_from(..)`](struct.SmbClient.html#method.upload_from).
+    pub fn upload_from_reader<R: Read + Seek, Q: AsRef<str>>(
+        &self,
+        reader: &mut R,
+        to: Q,
+        options: TransferOptions,
+    ) -> Result<u64> {
+        self.upload_from_reader_with_progress(reader, to, options, |_| {})
+    }
+
+    /// Same as [`upload_from_reader(..)`](struct.SmbClient.html#method.upload_from_reader)
+    /// calling `progress` after each chunk, file size is number of bytes
+    /// left in `reader`.
+    ///
+    /// ```rust
+    /// # use std::io::Cursor;
+    /// # fn main() {}
+    /// #
+    /// fn publish(client: &smbc::SmbClient, report: &str) -> smbc::Result<()> {
+    ///     let options = smbc::TransferOptions::default().create_parents(true);
+    ///     let mut reader = Cursor::new(report.as_bytes());
+    ///     client.upload_from_reader_with_progress(
+    ///         &mut reader,
+    ///         "smb://server/share/reports/2016/daily.txt",
+    ///         options,
+    ///         |p| eprint!("\r{}/{} bytes", p.file_bytes(), p.file_size()),
+    ///     )?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn upload_from_reader_with_progress<R, Q, F>(
+        &self,
+        reader: &mut R,
+        to: Q,
+        options: TransferOptions,
+        mut progress: F,
+    ) -> Result<u64>
+    where
+        R: Read + Seek,
+        Q: AsRef<str>,
+        F: FnMut(&Progress),
+    {
+        let to = to.as_ref();
+        trace!(target: "smbc", "uploading to {}", to);
+        #[allow(clippy::seek_from_current)] // `stream_position` requires Rust 1.51
+        let start = reader.seek(SeekFrom::Current(0))?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        let total = end.saturating_sub(start);
+
+        if options.create_parents {
+            if let Some(idx) = to.trim_end_matches('/').rfind('/') {
+                self.create_dir_all(&to[..idx])?;
+            }
+        }
+        let (mut dst, offset) = if options.resume {
+            let mut dst = self.open_rw(to)?;
+            let len = dst.metadata()?.len();
+            let offset = resume_offset(reader, start, total, &mut dst, len, options.verify_tail)?;
+            trace!(target: "smbc", "resuming upload to {} at {}", to, offset);
+            dst.set_len(offset)?;
+            dst.seek(SeekFrom::Start(offset))?;
+            (dst, offset)
+        } else {
+            (self.create(to)?, 0)
+        };
+        reader.seek(SeekFrom::Start(start + offset))?;
+        self.transfer(reader, &mut dst, options, |n| {
+            progress(&Progress::single(to, offset + n, total))
+        })
+    }
+
+    /// Copy rest of `src` to `dst` in chunks throttled to `options.max_rate`
+    fn transfer<R, W, F>(
+        &self,
+        src: &mut R,
+        dst: &mut W,
+        options: TransferOptions,
+        mut progress: F,
+    ) -> Result<u64>
+    where
+        R: Read,
+        W: Write,
+        F: FnMut(u64),
+    {
+        let rate = match options.max_rate {
+            Some(rate) => rate,
+            None => return stream_copy_with_progress(src, dst, self.chunk_size, progress),
+        };
+        // chunk of at most a second of transfer keeps bursts short
+        let chunk_size = cmp::min(self.chunk_size as u64, rate) as usize;
+        let started = Instant::now();
+        stream_copy_with_progress(src, dst, chunk_size, |copied| {
+            throttle(started, copied, rate);
+            progress(copied)
+        })
+    }
+
+    /// Download directory at SMB path `from` with all its content
+    /// to local directory `to`.
+    ///
+    /// Local directories are created as needed, existing files are overwritten.
+    /// Files are copied with [`download_to(..)`](struct.SmbClient.html#method.download_to)
+    /// using `options`, links and special files are skipped.
+    ///
+    /// Failure to copy file or subdirectory doesn't stop transfer, it's collected
+    /// to [`TreeTransfer::errors()`](struct.TreeTransfer.html#method.errors)
+    /// as `Error::Path` with source path. Only failure to list `from`
+    /// or create `to` is returned as error.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn backup(client: &smbc::SmbClient) -> smbc::Result<()> {
+    ///     let options = smbc::TransferOptions::default().preserve_mtime(true);
+    ///     let report = client.download_tree("smb://server/share/docs", "/backup/docs", options)?;
+    ///     for err in report.errors() {
+    ///         eprintln!("{}", err);
+    ///     }
+    ///     println!("{} files, {} bytes", report.files(), report.bytes());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn download_tree<P: AsRef<str>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+        options: TransferOptions,
+    ) -> Result<TreeTransfer> {
+        self.download_tree_with_progress(from, to, options, |_| {})
+    }
+
+    /// Same as [`download_tree(..)`](struct.SmbClient.html#method.download_tree)
+    /// calling `progress` after each chunk of every file.
+    pub fn download_tree_with_progress<P, Q, F>(
+        &self,
+        from: P,
+        to: Q,
+        options: TransferOptions,
+        mut progress: F,
+    ) -> Result<TreeTransfer>
+    where
+        P: AsRef<str>,
+        Q: AsRef<Path>,
+        F: FnMut(&Progress),
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let mut report = TreeTransfer::default();
+        at_local_path(to, fs::create_dir_all(to))?;
+        self.download_dir(from, to, options, &mut report, &mut progress)?;
+        Ok(report)
+    }
+
+    fn download_dir<F: FnMut(&Progress)>(
+        &self,
+        from: &str,
+        to: &Path,
+        options: TransferOptions,
+        report: &mut TreeTransfer,
+        progress: &mut F,
+    ) -> Result<()> {
+        trace!(target: "smbc", "downloading dir {} to {:?}", from, to);
+        for entry in at_path(from, self.read_dir_plus(from))? {
+            let (entry, metadata) = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    report
+                        .errors
+                        .push(Error::Path(from.to_owned(), Box::new(err)));
+                    continue;
+                }
+            };
+            let local = match local_entry_path(to, &entry.name) {
+                Ok(local) => local,
+                Err(err) => {
+                    report.errors.push(Error::Path(entry.path, Box::new(err)));
+                    continue;
+                }
+            };
+            let res = match entry.kind {
+                EntryType::Dir if metadata.file_type() != FileType::Symlink => {
+                    match fs::create_dir(&local) {
+                        Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+                        res => res.map_err(Error::from),
+                    }
+                    .and_then(|_| {
+                        report.dirs += 1;
+                        self.download_dir(&entry.path, &local, options, report, progress)
+                    })
+                }
+                EntryType::File => self
+                    .download_to_with_progress(&entry.path, &local, options, |file| {
+                        progress(&file.within(report, None, None))
+                    })
+                    .map(|bytes| {
+                        report.files += 1;
+                        report.bytes += bytes;
+                    }),
+                _ => Ok(()),
+            };
+            if let Err(err) = at_path(&entry.path, res) {
+                report.errors.push(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Upload local directory `from` with all its content to SMB path `to`.
+    ///
+    /// Remote directories are created as needed, existing files are overwritten.
+    /// Files are copied with [`upload_from(..)`](struct.SmbClient.html#method.upload_from)
+    /// using `options`, symbolic links and special files are skipped.
+    ///
+    /// Errors are collected the same way as in
+    /// [`download_tree(..)`](struct.SmbClient.html#method.download_tree).
+    pub fn upload_tree<P: AsRef<Path>, Q: AsRef<str>>(
+        &self,
+        from: P,
+        to: Q,
+        options: TransferOptions,
+    ) -> Result<TreeTransfer> {
+        self.upload_tree_with_progress(from, to, options, |_| {})
+    }
+
+    /// Same as [`upload_tree(..)`](struct.SmbClient.html#method.upload_tree)
+    /// calling `progress` after each chunk of every file.
+    pub fn upload_tree_with_progress<P, Q, F>(
+        &self,
+        from: P,
+        to: Q,
+        options: TransferOptions,
+        mut progress: F,
+    ) -> Result<TreeTransfer>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<str>,
+        F: FnMut(&Progress),
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let mut report = TreeTransfer::default();
+        self.create_dir_all(to)?;
+        let to = to.trim_end_matches('/');
+        self.upload_dir(from, to, options, &mut report, &mut progress)?;
+        Ok(report)
+    }
+
+    fn upload_dir<F: FnMut(&Progress)>(
+        &self,
+        from: &Path,
+        to: &str,
+        options: TransferOptions,
+        report: &mut TreeTransfer,
+        progress: &mut F,
+    ) -> Result<()> {
+        trace!(target: "smbc", "uploading dir {:?} to {}", from, to);
+        let local_path = |path: &Path| path.display().to_string();
+        for entry in at_path(&local_path(from), fs::read_dir(from).map_err(Error::from))? {
+            let res = entry.and_then(|entry| Ok((entry.path(), entry.file_type()?)));
+            let (local, file_type) = match res {
+                Ok(entry) => entry,
+                Err(err) => {
+                    report
+                        .errors
+                        .push(Error::Path(local_path(from), Box::new(err.into())));
+                    continue;
+                }
+            };
+            let name = match local.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name,
+                None => {
+                    let err = io::Error::new(io::ErrorKind::InvalidData, "non UTF-8 file name");
+                    report
+                        .errors
+                        .push(Error::Path(local_path(&local), Box::new(err.into())));
+                    continue;
+                }
+            };
+            let mut remote = format!("{}/", to);
+            percent_encode_to(name, &mut remote);
+
+            let res = if file_type.is_dir() {
+                match self.create_dir(&remote) {
+                    Err(ref err) if err.is_already_exists() => Ok(()),
+                    res => res,
+                }
+                .and_then(|_| {
+                    report.dirs += 1;
+                    self.upload_dir(&local, &remote, options, report, progress)
+                })
+            } else if file_type.is_file() {
+                self.upload_from_with_progress(&local, &remote, options, |file| {
+                    progress(&file.within(report, None, None))
+                })
+                .map(|bytes| {
+                    report.files += 1;
+                    report.bytes += bytes;
+                })
+            } else {
+                Ok(())
+            };
+            if let Err(err) = at_path(&local_path(&local), res) {
+                report.errors.push(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compare local directory `local` and SMB directory `remote` and compute
+    /// changes making destination (according to `direction`) the same as source.
+    ///
+    /// Files are compared by size and modification time (with second precision)
+    /// or by content, see [`SyncOptions`](struct.SyncOptions.html).
+    /// Missing destination is treated as empty. Nothing is changed until
+    /// [`ChangeSet::apply(..)`](struct.ChangeSet.html#method.apply) is called,
+    /// so changes could be reviewed (dry run).
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn dry_run(client: &smbc::SmbClient) -> smbc::Result<()> {
+    ///     let options = smbc::SyncOptions::default().delete(true);
+    ///     let changes = client.sync_plan(
+    ///         "/srv/www",
+    ///         "smb://server/backup/www",
+    ///         smbc::SyncDirection::Upload,
+    ///         options,
+    ///     )?;
+    ///     for change in changes.changes() {
+    ///         println!("{:?} {}", change.action(), change.path());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn sync_plan<P: AsRef<Path>, Q: AsRef<str>>(
+        &self,
+        local: P,
+        remote: Q,
+        direction: SyncDirection,
+        options: SyncOptions,
+    ) -> Result<ChangeSet> {
+        ChangeSet::new(self, local.as_ref(), remote.as_ref(), direction, options)
+    }
+
+    /// Make destination tree the same as source, like `rsync -r`.
+    ///
+    /// Same as [`sync_plan(..)`](struct.SmbClient.html#method.sync_plan)
+    /// followed by [`ChangeSet::apply(..)`](struct.ChangeSet.html#method.apply).
+    pub fn sync<P: AsRef<Path>, Q: AsRef<str>>(
+        &self,
+        local: P,
+        remote: Q,
+        direction: SyncDirection,
+        options: SyncOptions,
+    ) -> Result<TreeTransfer> {
+        self.sync_plan(local, remote, direction, options)?
+            .apply(self)
+    }
+
+    /// Same as [`sync(..)`](struct.SmbClient.html#method.sync)
+    /// calling `progress` after each chunk of every file.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn mirror(client: &smbc::SmbClient) -> smbc::Result<()> {
+    ///     let direction = smbc::SyncDirection::Download;
+    ///     let options = smbc::SyncOptions::default();
+    ///     client.sync_with_progress("/mirror", "smb://server/share", direction, options, |p| {
+    ///         let total = p.total_bytes().unwrap_or(0);
+    ///         eprint!("\r{} {}/{} bytes", p.path(), p.bytes(), total)
+    ///     })?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn sync_with_progress<P, Q, F>(
+        &self,
+        local: P,
+        remote: Q,
+        direction: SyncDirection,
+        options: SyncOptions,
+        progress: F,
+    ) -> Result<TreeTransfer>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<str>,
+        F: FnMut(&Progress),
+    {
+        self.sync_plan(local, remote, direction, options)?
+            .apply_with_progress(self, progress)
+    }
+
+    /// Copy `len` bytes from current position of `src` to `dst` on server side,
+    /// so data doesn't go through client (requires Samba 4.2+ and SMB2+).
+    ///
+    /// Both files should be opened by this client. Returns number of bytes
+    /// copied, fails with `Error::NotSupported` or other error if server
+    /// can't copy them.
+    pub fn splice(&self, src: &mut SmbFile, dst: &mut SmbFile, len: u64) -> Result<u64> {
+        let get_splice_fn = ffi::smbc_getFunctionSplice().ok_or(Error::NotSupported)?;
+        let splice_fn = self.get_fn(get_splice_fn)?;
+        trace!(target: "smbc", "splicing {} bytes from {} to {}", len, src.path, dst.path);
+        let copied = self.check(to_result_with_le(splice_fn(
+            self.ctx,
+            src.fd,
+            dst.fd,
+            len as off_t,
+            Some(splice_progress),
+            ptr::null_mut(),
+        )))?;
+        Ok(copied as u64)
+    }
+
+    /// Opens print job on printer share `printer` (e.g. `smb://server/printer`).
+    ///
+    /// Raw print data written to returned [`SmbPrintJob`](struct.SmbPrintJob.html)
+    /// is spooled and printed by server when job is finished or dropped.
+    /// Data isn't converted, so it should be in format printer
+    /// (or server driver) accepts.
+    ///
+    /// `libsmbclient` writes job as a regular file on printer share,
+    /// so it works with any protocol version server allows for printing.
+    /// Spooler errors are returned as usual I/O errors.
+    ///
+    /// ```rust
+    /// # use std::io::Write;
+    /// # fn main() {}
+    /// #
+    /// fn print_text(client: &smbc::SmbClient, text: &str) -> smbc::Result<()> {
+    ///     let mut job = client.open_print_job("smb://server/laser")?;
+    ///     job.write_all(text.as_bytes())?;
+    ///     job.write_all(b"\x0c")?;
+    ///     job.finish()
+    /// }
+    /// ```
+    pub fn open_print_job<'b, P: AsRef<str>>(&'b self, printer: P) -> Result<SmbPrintJob<'a, 'b>> {
+        let open_print_job_fn = self.get_fn(smbc_getFunctionOpenPrintJob)?;
+        let printer = printer.as_ref();
+        let c_printer = smb_cstring(printer)?;
+        trace!(target: "smbc", "opening print job on {:?}", c_printer);
+        let fd = result_from_handle(open_print_job_fn(self.ctx, c_printer.as_ptr()));
+        let file = self.check(fd.map(|fd| SmbFile {
+            smbc: self,
+            fd,
+            path: printer.to_owned(),
+            append: false,
+        }))?;
+        Ok(SmbPrintJob { file })
+    }
+
+    /// Prints local file `source` on printer share `printer`.
+    ///
+    /// Content is streamed to
+    /// [`open_print_job(..)`](struct.SmbClient.html#method.open_print_job)
+    /// as is, see it for data format.
+    pub fn print_local_file<P: AsRef<str>, Q: AsRef<Path>>(
+        &self,
+        printer: P,
+        source: Q,
+    ) -> Result<()> {
+        let mut source = ::std::fs::File::open(source)?;
+        let mut job = self.open_print_job(printer)?;
+        stream_copy(&mut source, &mut job, self.chunk_size)?;
+        job.finish()
+    }
+
+    /// List jobs queued on printer share `printer`.
+    ///
+    /// `libsmbclient` uses legacy RAP calls for it, so it works
+    /// only with servers allowing SMB1.
+    pub fn list_print_jobs<P: AsRef<str>>(&self, printer: P) -> Result<Vec<PrintJobInfo>> {
+        let list_print_jobs_fn = self.get_fn(smbc_getFunctionListPrintJobs)?;
+        let printer = smb_cstring(printer)?;
+        trace!(target: "smbc", "listing print jobs of {:?}", printer);
+        PRINT_JOBS.with(|jobs| jobs.borrow_mut().clear());
+        let res = list_print_jobs_fn(self.ctx, printer.as_ptr(), Some(collect_print_job));
+        let jobs = PRINT_JOBS.with(|jobs| mem::take(&mut *jobs.borrow_mut()));
+        self.check(to_result_with_le(res))?;
+        Ok(jobs)
+    }
+
+    /// Cancel job `id` queued on printer share `printer`.
+    ///
+    /// See [`list_print_jobs(..)`](struct.SmbClient.html#method.list_print_jobs).
+    pub fn cancel_print_job<P: AsRef<str>>(&self, printer: P, id: u16) -> Result<()> {
+        let unlink_print_job_fn = self.get_fn(smbc_getFunctionUnlinkPrintJob)?;
+        let printer = smb_cstring(printer)?;
+        trace!(target: "smbc", "cancelling print job {} on {:?}", id, printer);
+        self.check(to_result_with_le(unlink_print_job_fn(
+            self.ctx,
+            printer.as_ptr(),
+            c_int::from(id),
+        )))?;
+        Ok(())
+    }
+
+    /// Prints file at SMB path `source` on printer share `printer`.
+    ///
+    /// Content is read from `source` and sent to
+    /// [`open_print_job(..)`](struct.SmbClient.html#method.open_print_job)
+    /// by `libsmbclient`. For local files use
+    /// [`print_local_file(..)`](struct.SmbClient.html#method.print_local_file).
+    pub fn print_file<P: AsRef<str>, Q: AsRef<str>>(&self, printer: P, source: Q) -> Result<()> {
+        let print_file_fn = self.get_fn(smbc_getFunctionPrintFile)?;
+        let printer = smb_cstring(printer)?;
+        let source = smb_cstring(source)?;
+        trace!(target: "smbc", "printing {:?} on {:?}", source, printer);
+        self.check(to_result_with_le(print_file_fn(
+            self.ctx,
+            source.as_ptr(),
+            self.ctx,
+            printer.as_ptr(),
+        )))?;
+        Ok(())
+    }
+
+    /// Check if file or directory at SMB `path` exists.
+    ///
+    /// Returns `Ok(false)` only if server reports that path doesn't exist,
+    /// other errors (e.g. permission denied) are returned as is.
+    pub fn exists<P: AsRef<str>>(&self, path: P) -> Result<bool> {
+        match self.stat(path) {
+            Ok(_) => Ok(true),
+            Err(Error::NotFound) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Get file system statistics (capacity, free space) of share
+    /// containing SMB `path`.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn fits(client: &smbc::SmbClient, share: &str, len: u64) -> smbc::Result<bool> {
+    ///     let stats = client.statvfs(share)?;
+    ///     Ok(len <= stats.available_space())
+    /// }
+    /// ```
+    pub fn statvfs<P: AsRef<str>>(&self, path: P) -> Result<FsStats> {
+        let statvfs_fn = self.get_fn(ffi::smbc_getFunctionStatVFS)?;
+        let path = smb_cstring(path)?;
+        trace!(target: "smbc", "statvfs {:?}", path);
+        let mut st: ffi::statvfs = unsafe { mem::zeroed() };
+        let res = statvfs_fn(self.ctx, path.as_ptr() as *mut c_char, &mut st);
+        self.check(to_result_with_le(res))?;
+        Ok(FsStats::from_statvfs(&st))
+    }
+
+    fn stat<P: AsRef<str>>(&self, path: P) -> Result<libc::stat> {
+        let stat_fn = self.get_fn(smbc_getFunctionStat)?;
+        let path = smb_cstring(path)?;
+        trace!(target: "smbc", "stat {:?}", path);
+        let mut st: libc::stat = unsafe { mem::zeroed() };
+        self.check(to_result_with_le(stat_fn(self.ctx, path.as_ptr(), &mut st)))?;
+        Ok(st)
+    }
+
+    fn chmod<P: AsRef<str>>(&self, path: P, mode: mode_t) -> Result<()> {
+        let chmod_fn = self.get_fn(smbc_getFunctionChmod)?;
+        let path = smb_cstring(path)?;
+        trace!(target: "smbc", "chmod {:?} {:o}", path, mode);
+        self.check(to_result_with_le(chmod_fn(self.ctx, path.as_ptr(), mode)))?;
+        Ok(())
+    }
+
+    /// Change permissions of file or directory at SMB `path`,
+    /// like `std::fs::set_permissions`.
+    pub fn set_permissions<P: AsRef<str>>(&self, path: P, perm: Permissions) -> Result<()> {
+        self.chmod(path, perm.mode())
+    }
+
+    /// Set last access and modification times of file or directory
+    /// at SMB `path`, e.g. to preserve them when uploading.
+    pub fn set_file_times<P: AsRef<str>>(
+        &self,
+        path: P,
+        accessed: SystemTime,
+        modified: SystemTime,
+    ) -> Result<()> {
+        let utimes_fn = self.get_fn(smbc_getFunctionUtimes)?;
+        let path = smb_cstring(path)?;
+        trace!(target: "smbc", "utimes {:?} {:?} {:?}", path, accessed, modified);
+        let mut times = [to_timeval(accessed), to_timeval(modified)];
+        self.check(to_result_with_le(utimes_fn(
+            self.ctx,
+            path.as_ptr(),
+            times.as_mut_ptr(),
+        )))?;
+        Ok(())
+    }
+
+    /// Create new directory at SMB `path`
+    pub fn create_dir<P: AsRef<str>>(&self, path: P) -> Result<()> {
+        let mkdir_fn = self.get_fn(smbc_getFunctionMkdir)?;
+        let path = smb_cstring(path)?;
+        self.check(to_result_with_le(mkdir_fn(self.ctx, path.as_ptr(), 0o755)))?;
+        Ok(())
+    }
+
+    /// Create directory at SMB `path` with all missing parents,
+    /// like `std::fs::create_dir_all`.
+    ///
+    /// Directories are created one by one below share root,
+    /// existing ones are skipped.
+    pub fn create_dir_all<P: AsRef<str>>(&self, path: P) -> Result<()> {
+        let path = normalize_url(path.as_ref())?;
+        let path = path.trim_end_matches('/');
+        // skip `smb://host/share`, it can't be created
+        let root_len = path
+            .match_indices('/')
+            .nth(3)
+            .map_or(path.len(), |(idx, _)| idx);
+
+        let mut end = root_len;
+        while end < path.len() {
+            end = path[end + 1..]
+                .find('/')
+                .map_or(path.len(), |idx| end + 1 + idx);
+            let dir = &path[..end];
+            match self.create_dir(dir) {
+                Ok(()) => trace!(target: "smbc", "created {}", dir),
+                Err(ref err) if err.is_already_exists() && self.metadata(dir)?.is_dir() => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete directory at SMB `path`.
+    ///
+    /// Directory should be empty to delete it.
+    pub fn remove_dir<P: AsRef<str>>(&self, path: P) -> Result<()> {
+        let rmdir_fn = self.get_fn(smbc_getFunctionRmdir)?;
+        let path = smb_cstring(path)?;
+        self.check(to_result_with_le(rmdir_fn(self.ctx, path.as_ptr())))?;
+        Ok(())
+    }
+
+    /// Delete directory at SMB `path` with all its content,
+    /// like `std::fs::remove_dir_all`.
+    ///
+    /// Entries are removed depth-first, error is returned as `Error::Path`
+    /// with path which failed to be listed or removed. Symlinks to
+    /// directories are removed themselves, their targets aren't touched.
+    pub fn remove_dir_all<P: AsRef<str>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        // collected so directory isn't modified while it's listed
+        let entries: Vec<DirEntry> =
+            at_path(path, self.read_dir(path).and_then(|it| it.collect()))?;
+        for entry in entries {
+            let is_dir = entry.entry_type() == EntryType::Dir
+                && at_path(entry.path(), self.metadata(entry.path()))?.file_type()
+                    != FileType::Symlink;
+            if is_dir {
+                self.remove_dir_all(entry.path())?;
+            } else {
+                at_path(entry.path(), self.remove_file(entry.path()))?;
+            }
+        }
+        at_path(path, self.remove_dir(path))
+    }
+
+    /// Delete file at SMB `path`.
+    pub fn remove_file<P: AsRef<str>>(&self, path: P) -> Result<()> {
+        let unlink_fn = self.get_fn(smbc_getFunctionUnlink)?;
+        let path = smb_cstring(path)?;
+        trace!(target: "smbc", "removing {:?}", path);
+        self.check(to_result_with_le(unlink_fn(self.ctx, path.as_ptr())))?;
+        Ok(())
+    }
+
+    /// Rename (move) file or directory at SMB path `from` to `to`.
+    ///
+    /// Both paths should be on the same share, otherwise `Error::CrossShare`
+    /// is returned and caller should copy and delete instead.
+    pub fn rename<P: AsRef<str>, Q: AsRef<str>>(&self, from: P, to: Q) -> Result<()> {
+        let rename_fn = self.get_fn(smbc_getFunctionRename)?;
+        let from = smb_cstring(from)?;
+        let to = smb_cstring(to)?;
+        trace!(target: "smbc", "renaming {:?} to {:?}", from, to);
+        // both paths are resolved by the same context
+        self.check(to_result_with_le(rename_fn(
+            self.ctx,
+            from.as_ptr(),
+            self.ctx,
+            to.as_ptr(),
+        )))?;
+        Ok(())
+    }
+
+    /// List shares exported by server `host` (host name or `smb://host` URL).
+    ///
+    /// Hidden (administrative) shares like `C$` or `IPC$` are included,
+    /// see [`ShareInfo::is_hidden`](struct.ShareInfo.html#method.is_hidden).
+    pub fn list_shares<P: AsRef<str>>(&self, host: P) -> Result<Vec<ShareInfo>> {
+        let mut shares = Vec::new();
+        for entry in self.read_dir(browse_url(host.as_ref()))? {
+            let entry = entry?;
+            if let EntryType::Share(kind) = entry.kind {
+                shares.push(ShareInfo {
+                    name: entry.name,
+                    kind,
+                    comment: entry.comment,
+                });
+            }
+        }
+        Ok(shares)
+    }
+
+    /// List workgroups (domains) visible on local network.
+    ///
+    /// Relies on master browser, so result may be empty on networks
+    /// without NetBIOS browsing (e.g. SMB1 disabled).
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn map_lan(client: &smbc::SmbClient) -> smbc::Result<()> {
+    ///     for workgroup in client.list_workgroups()? {
+    ///         for server in client.list_servers(workgroup.name())? {
+    ///             println!("{}\\{} {}", workgroup.name(), server.name(), server.comment());
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn list_workgroups(&self) -> Result<Vec<WorkgroupInfo>> {
+        let mut workgroups = Vec::new();
+        for entry in self.read_dir("smb://")? {
+            let entry = entry?;
+            if entry.kind == EntryType::Workgroup {
+                workgroups.push(WorkgroupInfo {
+                    name: entry.name,
+                    comment: entry.comment,
+                });
+            }
+        }
+        Ok(workgroups)
+    }
+
+    /// List servers of `workgroup` (name or `smb://workgroup` URL).
+    pub fn list_servers<P: AsRef<str>>(&self, workgroup: P) -> Result<Vec<ServerInfo>> {
+        let mut servers = Vec::new();
+        for entry in self.read_dir(browse_url(workgroup.as_ref()))? {
+            let entry = entry?;
+            if entry.kind == EntryType::Server {
+                servers.push(ServerInfo {
+                    name: entry.name,
+                    comment: entry.comment,
+                });
+            }
+        }
+        Ok(servers)
+    }
+
+    /// Opens [`SmbDirectory`](struct.SmbDirectory.html) defined by SMB `path`.
+    pub fn open_dir<'b, P: AsRef<str>>(&'b self, path: P) -> Result<SmbDirectory<'a, 'b>> {
+        let opendir_fn = self.get_fn(smbc_getFunctionOpendir)?;
+        let path = smb_cstring(path)?;
+        trace!(target: "smbc", "opening dir {:?}", path);
+        let fd = result_from_handle(opendir_fn(self.ctx, path.as_ptr()));
+        self.check(fd.map(|fd| SmbDirectory { smbc: self, fd }))
+    }
+
+    /// Iterate over entries of directory at SMB `path` with their metadata.
+    ///
+    /// Uses `readdirplus` which gets attributes with listing itself,
+    /// so there is no extra request per entry. If `libsmbclient` can't
+    /// provide it (no support or not a file share listing) falls back
+    /// to `readdir` and `stat` of each entry.
+    ///
+    /// `.` and `..` are skipped.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn total_size(client: &smbc::SmbClient, dir: &str) -> smbc::Result<u64> {
+    ///     let mut total = 0;
+    ///     for entry in client.read_dir_plus(dir)? {
+    ///         let (_entry, metadata) = entry?;
+    ///         if metadata.is_file() {
+    ///             total += metadata.len();
+    ///         }
+    ///     }
+    ///     Ok(total)
+    /// }
+    /// ```
+    pub fn read_dir_plus<'b, P: AsRef<str>>(&'b self, path: P) -> Result<ReadDirPlus<'a, 'b>> {
+        let inner = self.read_dir(path)?;
+        // missing before Samba 4.5, `readdir` is used then
+        let readdirplus_fn = match ffi::smbc_getFunctionReaddirPlus() {
+            Some(get_readdirplus_fn) => unsafe { get_readdirplus_fn(self.ctx) },
+            None => None,
+        };
+        Ok(ReadDirPlus {
+            inner,
+            readdirplus_fn,
+            started: false,
+        })
+    }
+
+    /// List entries of directory at SMB `path` with their metadata,
+    /// e.g. for `ls -l` like view.
+    ///
+    /// Same as collecting [`read_dir_plus(..)`](struct.SmbClient.html#method.read_dir_plus),
+    /// so without `readdirplus` each entry is stat'ed. First error stops listing.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn ls_l(client: &smbc::SmbClient, dir: &str) -> smbc::Result<()> {
+    ///     for (entry, metadata) in client.list_dir_with_metadata(dir)? {
+    ///         println!(
+    ///             "{:o} {:>10} {:?} {}",
+    ///             metadata.permissions().mode(),
+    ///             metadata.len(),
+    ///             metadata.modified(),
+    ///             entry.name()
+    ///         );
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn list_dir_with_metadata<P: AsRef<str>>(
+        &self,
+        path: P,
+    ) -> Result<Vec<(DirEntry, Metadata)>> {
+        self.read_dir_plus(path)?.collect()
+    }
+
+    /// Walk directory tree under SMB `root` recursively, like `walkdir`.
+    ///
+    /// Yields `(depth, entry, metadata)` for every entry under `root`
+    /// (not `root` itself, its entries have depth `1`). Directories are
+    /// yielded before their content and opened only when reached,
+    /// so memory use doesn't depend on tree size. Errors (e.g. permission
+    /// denied on subdirectory) are yielded and walk continues.
+    ///
+    /// If `root` is server, its disk shares are walked; hidden ones
+    /// (like `C$`) are skipped unless enabled with
+    /// [`hidden_shares(true)`](struct.Walk.html#method.hidden_shares).
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn index(client: &smbc::SmbClient) -> smbc::Result<()> {
+    ///     for entry in client.walk("smb://fs1/projects").max_depth(3).sort_by_name(true) {
+    ///         let (depth, entry, metadata) = entry?;
+    ///         println!("{:indent$}{} {}", "", entry.name(), metadata.len(), indent = depth * 2);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::legacy_numeric_constants)] // associated consts require Rust 1.43
+    pub fn walk<'b, P: AsRef<str>>(&'b self, root: P) -> Walk<'a, 'b> {
+        Walk {
+            smbc: self,
+            pending: Some(root.as_ref().to_owned()),
+            stack: Vec::new(),
+            max_depth: usize::max_value(),
+            follow_links: false,
+            sort_by_name: false,
+            hidden_shares: false,
+        }
+    }
+
+    /// Load directory tree under SMB `root` into memory, e.g. to render it
+    /// in file browser.
+    ///
+    /// Tree is traversed with [`walk(..)`](struct.SmbClient.html#method.walk)
+    /// defaults down to `max_depth` (`0` gives `root` alone, `1` its entries
+    /// and so on). Children are sorted by name. First error stops loading,
+    /// as does tree with more than 100000 entries, so use `walk` for
+    /// large trees.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn print(node: &smbc::TreeNode, indent: usize) {
+    ///     println!("{:indent$}{}", "", node.name(), indent = indent);
+    ///     for child in node.children() {
+    ///         print(child, indent + 2);
+    ///     }
+    /// }
+    ///
+    /// fn show(client: &smbc::SmbClient) -> smbc::Result<()> {
+    ///     print(&client.read_tree("smb://fs1/projects", 2)?, 0);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn read_tree<P: AsRef<str>>(&self, root: P, max_depth: usize) -> Result<TreeNode> {
+        let root = root.as_ref();
+        let metadata = self.metadata(root)?;
+        let url = normalize_url(root)?;
+        // `rsplit` yields at least one (maybe empty) item
+        let name = url["smb://".len()..]
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next();
+        let name = percent_decode(name.unwrap_or(""))?;
+        let root_node = TreeNode {
+            name,
+            is_dir: metadata.is_dir(),
+            children: Vec::new(),
+            metadata,
+        };
+        trace!(target: "smbc", "reading tree under {} down to {}", root, max_depth);
+
+        let walk = self.walk(root).max_depth(max_depth).sort_by_name(true);
+        let nodes = walk.map(|entry| {
+            entry.map(|(depth, entry, metadata)| {
+                let node = TreeNode {
+                    name: entry.name,
+                    is_dir: metadata.is_dir(),
+                    children: Vec::new(),
+                    metadata,
+                };
+                (depth, node)
+            })
+        });
+        build_tree(root_node, nodes, TREE_MAX_NODES)
+    }
+
+    /// Total size in bytes, number of files and directories under SMB `path`,
+    /// like `du -s`.
+    ///
+    /// Tree is traversed with [`walk(..)`](struct.SmbClient.html#method.walk)
+    /// defaults, so links aren't followed. First error stops counting.
+    pub fn dir_size<P: AsRef<str>>(&self, path: P) -> Result<DirSize> {
+        self.dir_size_with_progress(path, |_| {})
+    }
+
+    /// Same as [`dir_size(..)`](struct.SmbClient.html#method.dir_size)
+    /// calling `progress` with totals so far after each entry.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn report(client: &smbc::SmbClient, path: &str) -> smbc::Result<()> {
+    ///     let size = client.dir_size_with_progress(path, |so_far| {
+    ///         if so_far.files() % 1000 == 0 {
+    ///             eprintln!("{} files, {} bytes", so_far.files(), so_far.bytes());
+    ///         }
+    ///     })?;
+    ///     println!("{}: {} bytes in {} files", path, size.bytes(), size.files());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn dir_size_with_progress<P, F>(&self, path: P, mut progress: F) -> Result<DirSize>
+    where
+        P: AsRef<str>,
+        F: FnMut(&DirSize),
+    {
+        let mut size = DirSize::default();
+        for entry in self.walk(path) {
+            let (_, _, metadata) = entry?;
+            match metadata.file_type() {
+                FileType::Dir => size.dirs += 1,
+                _ => {
+                    size.files += 1;
+                    size.bytes += metadata.len();
+                }
+            }
+            progress(&size);
+        }
+        Ok(size)
+    }
+
+    /// Find SMB URLs matching wildcard `pattern`, like `glob` crate.
+    ///
+    /// `*` matches any part of name, `?` any character, `[a-z]` (`[!a-z]`)
+    /// characters (not) in class and `**` any number of directories.
+    /// Names are compared ASCII case-insensitively as Windows servers do.
+    /// Wildcards can be used in share and path components.
+    ///
+    /// Tree is walked from the last component without wildcards, directories
+    /// which can't contain matches aren't entered. Without `**` walk depth
+    /// is limited by number of components too. Hidden shares are skipped.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn remove_old_logs(client: &smbc::SmbClient) -> smbc::Result<()> {
+    ///     for url in client.glob("smb://fs1/logs/**/*.log.[0-9]")? {
+    ///         client.remove_file(url?)?;
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn glob<'b>(&'b self, pattern: &str) -> Result<Glob<'a, 'b>> {
+        Glob::new(self, pattern)
+    }
+
+    /// Watch directory at SMB `path` for changes matching `filter`
+    /// (requires Samba 4.7+).
+    ///
+    /// Blocks until `callback` returns `false`,
+    /// see [`SmbDirectory::watch(..)`](struct.SmbDirectory.html#method.watch).
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn wait_for(client: &smbc::SmbClient, dir: &str, name: &str) -> smbc::Result<()> {
+    ///     client.watch(dir, false, smbc::NotifyFilter::FILE_NAME, |event| {
+    ///         !(event.action() == smbc::ChangeAction::Added && event.filename() == name)
+    ///     })
+    /// }
+    /// ```
+    pub fn watch<P, F>(
+        &self,
+        path: P,
+        recursive: bool,
+        filter: NotifyFilter,
+        callback: F,
+    ) -> Result<()>
+    where
+        P: AsRef<str>,
+        F: FnMut(ChangeEvent) -> bool,
+    {
+        self.open_dir(path)?.watch(recursive, filter, callback)
+    }
+
+    /// Watch directory at SMB `path` for changes matching `filter`
+    /// iterating over them (requires Samba 4.7+).
+    ///
+    /// Each `next` blocks until server reports change.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn log_changes(client: &smbc::SmbClient, dir: &str) -> smbc::Result<()> {
+    ///     let filter = smbc::NotifyFilter::FILE_NAME | smbc::NotifyFilter::LAST_WRITE;
+    ///     for event in client.watcher(dir, true, filter)? {
+    ///         let event = event?;
+    ///         println!("{:?} {}", event.action(), event.filename());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn watcher<'b, P: AsRef<str>>(
+        &'b self,
+        path: P,
+        recursive: bool,
+        filter: NotifyFilter,
+    ) -> Result<Watcher<'a, 'b>> {
+        Ok(Watcher {
+            dir: self.open_dir(path)?,
+            recursive,
+            filter,
+            pending: VecDeque::new(),
+            failed: false,
+        })
+    }
+
+    /// Iterate over entries of directory at SMB `path`.
+    ///
+    /// `.` and `..` are skipped.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn list(client: &smbc::SmbClient) -> smbc::Result<()> {
+    ///     for entry in client.read_dir("smb://127.0.0.1/share/dir")? {
+    ///         let entry = entry?;
+    ///         println!("{:?} {}", entry.entry_type(), entry.path());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn read_dir<'b, P: AsRef<str>>(&'b self, path: P) -> Result<ReadDir<'a, 'b>> {
+        let path = normalize_url(path.as_ref())?.into_owned();
+        let dir = self.open_dir(&path)?;
+        Ok(ReadDir { dir, path })
+    }
+
+    /// Get extended attribute `name` of file or directory at SMB `path`.
+    ///
+    /// `libsmbclient` exposes DOS attributes and NT security descriptors
+    /// this way, e.g. `system.dos_attr.mode` or `system.nt_sec_desc.*`
+    /// (see `libsmbclient.h` for full list of supported names).
+    ///
+    /// Value size is queried first, buffer is grown and request is retried
+    /// if value still doesn't fit. Trailing NUL isn't included.
+    pub fn get_xattr<P: AsRef<str>>(&self, path: P, name: &str) -> Result<Vec<u8>> {
+        let getxattr_fn = self.get_fn(smbc_getFunctionGetxattr)?;
+        let path = smb_cstring(path)?;
+        let name = cstring(name)?;
+        trace!(target: "smbc", "getxattr {:?} of {:?}", name, path);
+
+        let (mut buf, _) = with_xattr_buf(|buf, len| {
+            self.check(to_result_with_le(getxattr_fn(
+                self.ctx,
+                path.as_ptr(),
+                name.as_ptr(),
+                buf as *const c_void,
+                len as _,
+            )))
+        })?;
+        // value is NUL-terminated, return value isn't reliable across versions
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Get extended attribute `name` of file or directory at SMB `path` as string.
+    ///
+    /// See [`get_xattr(..)`](struct.SmbClient.html#method.get_xattr),
+    /// invalid UTF-8 is replaced.
+    pub fn getxattr<P: AsRef<str>>(&self, path: P, name: &str) -> Result<String> {
+        let value = self.get_xattr(path, name)?;
+        Ok(String::from_utf8_lossy(&value).into_owned())
+    }
+
+    /// Set extended attribute `name` of file or directory at SMB `path` to `value`.
+    ///
+    /// `value` must not contain NUL bytes, terminating one is added.
+    ///
+    /// Fails with `EEXIST` or `ENODATA` depending on `flags`,
+    /// see [`XattrFlags`](enum.XattrFlags.html).
+    pub fn set_xattr<P: AsRef<str>>(
+        &self,
+        path: P,
+        name: &str,
+        value: &[u8],
+        flags: XattrFlags,
+    ) -> Result<()> {
+        let setxattr_fn = self.get_fn(smbc_getFunctionSetxattr)?;
+        let path = smb_cstring(path)?;
+        let name = cstring(name)?;
+        // libsmbclient expects value to be NUL-terminated
+        let value = CString::new(value)?;
+        trace!(target: "smbc", "setxattr {:?} of {:?} to {:?}", name, path, value);
+
+        let value = value.as_bytes_with_nul();
+        self.check(to_result_with_le(setxattr_fn(
+            self.ctx,
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr() as *const c_void,
+            value.len() as _,
+            flags.to_flags(),
+        )))?;
+        Ok(())
+    }
+
+    /// Set extended attribute `name` of file or directory at SMB `path` to `value`.
+    ///
+    /// E.g. setting `system.dos_attr.mode` to `0x02` makes file hidden.
+    /// See [`set_xattr(..)`](struct.SmbClient.html#method.set_xattr).
+    pub fn setxattr<P: AsRef<str>>(
+        &self,
+        path: P,
+        name: &str,
+        value: &str,
+        flags: XattrFlags,
+    ) -> Result<()> {
+        self.set_xattr(path, name, value.as_bytes(), flags)
+    }
+
+    /// List names of extended attributes supported for file or directory at SMB `path`.
+    ///
+    /// `libsmbclient` reports the same fixed set of `system.*` names for any entry.
+    pub fn list_xattrs<P: AsRef<str>>(&self, path: P) -> Result<Vec<String>> {
+        let listxattr_fn = self.get_fn(smbc_getFunctionListxattr)?;
+        let path = smb_cstring(path)?;
+        trace!(target: "smbc", "listxattr of {:?}", path);
+
+        let (buf, len) = with_xattr_buf(|buf, len| {
+            self.check(to_result_with_le(listxattr_fn(
+                self.ctx,
+                path.as_ptr(),
+                buf as *mut c_char,
+                len as _,
+            )))
+        })?;
+        let len = cmp::min(len, buf.len());
+        Ok(buf[..len]
+            .split(|&b| b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect())
+    }
+
+    /// Remove extended attribute `name` of file or directory at SMB `path`.
+    ///
+    /// Only some attributes can be removed, e.g. ACEs of `system.nt_sec_desc.acl`.
+    pub fn remove_xattr<P: AsRef<str>>(&self, path: P, name: &str) -> Result<()> {
+        let removexattr_fn = self.get_fn(smbc_getFunctionRemovexattr)?;
+        let path = smb_cstring(path)?;
+        let name = cstring(name)?;
+        trace!(target: "smbc", "removexattr {:?} of {:?}", name, path);
+        self.check(to_result_with_le(removexattr_fn(
+            self.ctx,
+            path.as_ptr(),
+            name.as_ptr(),
+        )))?;
+        Ok(())
+    }
+
+    /// Get DOS attributes of file or directory at SMB `path`.
+    pub fn dos_attributes<P: AsRef<str>>(&self, path: P) -> Result<DosAttributes> {
+        DosAttributes::parse(&self.getxattr(path, DOS_ATTR_MODE_XATTR)?)
+    }
+
+    /// Set DOS attributes of file or directory at SMB `path` to `attrs`.
+    ///
+    /// Attributes not in `attrs` are cleared, use
+    /// [`dos_attributes(..)`](struct.SmbClient.html#method.dos_attributes)
+    /// to change single attribute:
+    ///
+    /// ```rust
+    /// # fn hide(client: &smbc::SmbClient, path: &str) -> smbc::Result<()> {
+    ///     let attrs = client.dos_attributes(path)?;
+    ///     client.set_dos_attributes(path, attrs | smbc::DosAttributes::HIDDEN)?;
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn set_dos_attributes<P: AsRef<str>>(&self, path: P, attrs: DosAttributes) -> Result<()> {
+        let mode = format!("0x{:x}", attrs.0);
+        self.setxattr(path, DOS_ATTR_MODE_XATTR, &mode, XattrFlags::None)
+    }
+
+    /// Get DOS attributes with creation, access, write and change times
+    /// of file or directory at SMB `path` in one request.
+    ///
+    /// Creation time is reported only if client is built with
+    /// [`full_time_names(true)`](struct.SmbClientBuilder.html#method.full_time_names).
+    pub fn dos_info<P: AsRef<str>>(&self, path: P) -> Result<DosInfo> {
+        DosInfo::parse(&self.getxattr(path, DOS_ATTR_ALL_XATTR)?)
+    }
+
+    /// Set DOS attributes and timestamps of file or directory at SMB `path`.
+    ///
+    /// Timestamps which are `None` in `info` are left unchanged.
+    /// Setting creation time requires client built with
+    /// [`full_time_names(true)`](struct.SmbClientBuilder.html#method.full_time_names),
+    /// otherwise `Error::NotSupported` is returned.
+    ///
+    /// ```rust
+    /// # use std::time::SystemTime;
+    /// # fn touch_hidden(client: &smbc::SmbClient, path: &str) -> smbc::Result<()> {
+    ///     let info = client.dos_info(path)?;
+    ///     let attrs = info.attributes() | smbc::DosAttributes::HIDDEN;
+    ///     let info = info.with_attributes(attrs).with_written(SystemTime::now());
+    ///     client.set_dos_info(path, &info)?;
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn set_dos_info<P: AsRef<str>>(&self, path: P, info: &DosInfo) -> Result<()> {
+        let full_time_names = unsafe { smbc_getOptionFullTimeNames(self.ctx) } == SMBC_TRUE;
+        if info.created.is_some() && !full_time_names {
+            return Err(Error::NotSupported);
+        }
+        let value = info.to_xattr(full_time_names);
+        self.setxattr(path, DOS_ATTR_ALL_XATTR, &value, XattrFlags::None)
+    }
+
+    /// Get NT security descriptor (owner, group and ACL) of file
+    /// or directory at SMB `path` with numeric SIDs.
+    ///
+    /// Returns `Error::NotSupported` if share doesn't support NT ACLs.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn report(client: &smbc::SmbClient, path: &str) -> smbc::Result<()> {
+    ///     let sd = client.security_descriptor(path)?;
+    ///     for ace in sd.aces() {
+    ///         println!("{} {:?} 0x{:08x}", ace.sid(), ace.kind(), ace.mask());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn security_descriptor<P: AsRef<str>>(&self, path: P) -> Result<SecurityDescriptor> {
+        self.getxattr(path, NT_SEC_DESC_XATTR)?.parse()
+    }
+
+    /// Resolve SIDs used in NT security descriptors of `paths`
+    /// (owners, groups and ACE trustees) to `DOMAIN\name` form.
+    ///
+    /// `libsmbclient` has no standalone lookup, so descriptor of each path
+    /// is read both with numeric and with resolved SIDs, which makes it
+    /// two requests per path. SIDs server can't resolve aren't in result.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn report(client: &smbc::SmbClient, dir: &str) -> smbc::Result<()> {
+    ///     let paths = client
+    ///         .read_dir(dir)?
+    ///         .map(|entry| entry.map(|e| e.path().to_owned()))
+    ///         .collect::<smbc::Result<Vec<_>>>()?;
+    ///     let names = client.resolve_sids(&paths)?;
+    ///     for path in &paths {
+    ///         for ace in client.security_descriptor(path)?.aces() {
+    ///             let sid = ace.sid().to_string();
+    ///             let name = names.get(ace.sid()).unwrap_or(&sid);
+    ///             println!("{}: {} 0x{:08x}", path, name, ace.mask());
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn resolve_sids<P: AsRef<str>>(&self, paths: &[P]) -> Result<HashMap<Sid, String>> {
+        let mut names = HashMap::new();
+        for path in paths {
+            let path = path.as_ref();
+            let sd = self.security_descriptor(path)?;
+            let named = self.getxattr(path, NT_SEC_DESC_NAMES_XATTR)?;
+            names.extend(acl::sid_names(&sd, &named)?);
+        }
+        Ok(names)
+    }
+
+    /// Replace NT security descriptor of file or directory at SMB `path` with `sd`.
+    ///
+    /// Descriptor is sent with numeric SIDs, owner and group which are `None`
+    /// in `sd` are left unchanged (changing them requires take ownership right).
+    ///
+    /// See [`security_descriptor(..)`](struct.SmbClient.html#method.security_descriptor).
+    pub fn set_security_descriptor<P: AsRef<str>>(
+        &self,
+        path: P,
+        sd: &SecurityDescriptor,
+    ) -> Result<()> {
+        self.setxattr(path, NT_SEC_DESC_XATTR, &sd.to_string(), XattrFlags::None)
+    }
+
+    /// Add `ace` to ACL of file or directory at SMB `path`.
+    ///
+    /// Descriptor is read, modified and written back with the same revision,
+    /// owner and group aren't touched. Does nothing if equal entry exists.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn grant_read(client: &smbc::SmbClient, path: &str, sid: smbc::Sid) -> smbc::Result<()> {
+    ///     // FILE_GENERIC_READ | FILE_EXECUTE
+    ///     let ace = smbc::Ace::new(sid, smbc::AceType::Allowed, 0, 0x001200a9);
+    ///     client.add_ace(path, &ace)
+    /// }
+    /// ```
+    pub fn add_ace<P: AsRef<str>>(&self, path: P, ace: &Ace) -> Result<()> {
+        self.modify_acl(path.as_ref(), |sd| {
+            let changed = !sd.aces().contains(ace);
+            sd.add_ace(ace.clone());
+            changed
+        })
+    }
+
+    /// Remove entries equal to `ace` from ACL of file or directory at SMB `path`.
+    ///
+    /// Entry matches only if SID, type, flags and mask are the same.
+    /// Does nothing if there are no such entries.
+    pub fn remove_ace<P: AsRef<str>>(&self, path: P, ace: &Ace) -> Result<()> {
+        self.modify_acl(path.as_ref(), |sd| sd.remove_ace(ace))
+    }
+
+    /// Read descriptor, apply `f` and write ACL back if `f` returns `true`.
+    fn modify_acl<F>(&self, path: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut SecurityDescriptor) -> bool,
+    {
+        let mut sd = self.security_descriptor(path)?;
+        if !f(&mut sd) {
+            return Ok(());
+        }
+        sd.set_owner(None);
+        sd.set_group(None);
+        trace!(target: "smbc", "setting acl of {}: {}", path, sd);
+        self.set_security_descriptor(path, &sd)
+    }
+
+    /// Replace content of file at SMB `path` with `contents`
+    /// keeping its NT security descriptor (ACL).
+    ///
+    /// Descriptor is read before file is truncated and its ACL is reapplied
+    /// after write, so custom ACL isn't replaced with inherited defaults.
+    /// Owner and group aren't written back, changing them needs more
+    /// rights than changing ACL. If file doesn't exist or descriptor
+    /// can't be read (e.g. share has no NT ACLs) it is just written.
+    pub fn overwrite_preserving_acl<P: AsRef<str>>(&self, path: P, contents: &[u8]) -> Result<()> {
+        let path = path.as_ref();
+        let sd = acl_to_restore(path, self.security_descriptor(path));
+
+        {
+            let mut file = self.create(path)?;
+            file.write_all(contents)?;
+        }
+
+        if let Some(sd) = sd {
+            trace!(target: "smbc", "restoring acl of {}: {}", path, sd);
+            self.set_security_descriptor(path, &sd)?;
+        }
+        Ok(())
+    }
+
+    /// Get native function from context.
+    ///
+    /// Every native call is preceded by this, so it also drops auth failure
+    /// left by previous call which wasn't checked.
+    #[allow(improper_ctypes_definitions)]
+    fn get_fn<T>(
+        &self,
+        get_func: unsafe extern "C" fn(*mut SMBCCTX) -> Option<T>,
+    ) -> io::Result<T> {
+        self.take_auth_failure();
+        unsafe { get_func(self.ctx).ok_or(io::Error::from_raw_os_error(libc::EINVAL)) }
+    }
+} // 2}}}
 
 impl<'a> Drop for SmbClient<'a> {
     // {{{2
-    /// Destroy `SmbClient` and close all connections.
+    /// Destroy `SmbClient` and close all connections.
+    fn drop(&mut self) {
+        trace!(target: "smbc", "closing smbclient");
+        unsafe {
+            smbc_free_context(self.ctx, 1 as c_int);
+        }
+    }
+} // 2}}}
+
+impl SmbClient<'static> {
+    // {{{2
+    /// Download file at SMB path `from` to local file `to` reading
+    /// up to `streams` byte ranges concurrently, returns number of bytes copied.
+    ///
+    /// Single SMB stream often can't saturate fast link, so file is split
+    /// into `streams` ranges (at most one per
+    /// [`io_chunk_size`](struct.SmbClientBuilder.html#method.io_chunk_size))
+    /// which are read through own handles and written into place in local file.
+    /// `libsmbclient` context can't be used concurrently, so each range except
+    /// first one is read by [`try_clone`](struct.SmbClient.html#method.try_clone)
+    /// of this client in its own thread, hence `'static` auth function.
+    ///
+    /// Local file is created or truncated. On error it's left incomplete.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn fetch(client: &smbc::SmbClient<'static>) -> smbc::Result<()> {
+    ///     client.download_parallel("smb://server/share/disk.img", "/tmp/disk.img", 4)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn download_parallel<P: AsRef<str>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+        streams: usize,
+    ) -> Result<u64> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let total = self.metadata(from)?.len();
+        let ranges = byte_ranges(total, self.chunk_size as u64, streams as u64);
+        trace!(target: "smbc", "downloading {} to {:?} in {} streams", from, to, ranges.len());
+
+        at_local_path(to, fs::File::create(to))?.set_len(total)?;
+
+        let mut workers = Vec::new();
+        let mut res = Ok(0);
+        for &(start, len) in &ranges[1..] {
+            let client = match self.try_clone() {
+                Ok(client) => client,
+                Err(err) => {
+                    // started workers are still joined below
+                    res = Err(err);
+                    break;
+                }
+            };
+            let (from, to) = (from.to_owned(), to.to_owned());
+            workers.push(thread::spawn(move || {
+                client.download_range(&from, &to, start, len)
+            }));
+        }
+        if res.is_ok() {
+            let (start, len) = ranges[0];
+            res = self.download_range(from, to, start, len);
+        }
+        for worker in workers {
+            let copied = worker
+                .join()
+                .unwrap_or_else(|err| panic::resume_unwind(err));
+            res = match (res, copied) {
+                (Ok(sum), Ok(copied)) => Ok(sum + copied),
+                (Err(err), _) | (_, Err(err)) => Err(err),
+            };
+        }
+        res
+    }
+
+    /// Visit every entry under SMB `root` listing up to `workers`
+    /// directories concurrently, e.g. to index large share.
+    ///
+    /// Each worker thread lists directories with its own
+    /// [`try_clone`](struct.SmbClient.html#method.try_clone) of this client
+    /// (hence `'static` auth function). `visitor` is called on calling
+    /// thread in no particular order, with directories before their
+    /// content. Directories are listed with
+    /// [`read_dir_plus(..)`](struct.SmbClient.html#method.read_dir_plus)
+    /// and entered like with [`walk(..)`](struct.SmbClient.html#method.walk)
+    /// defaults, i.e. hidden shares and links are skipped. First error
+    /// stops the walk.
+    ///
+    /// At most `workers` listings are in flight at once, so memory use
+    /// depends on size of directories rather than on size of tree.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn count_files(client: &smbc::SmbClient<'static>) -> smbc::Result<u64> {
+    ///     let mut files = 0;
+    ///     client.walk_parallel("smb://fs1/archive", 8, |entry| {
+    ///         if entry.entry_type() == smbc::EntryType::File {
+    ///             files += 1;
+    ///         }
+    ///     })?;
+    ///     Ok(files)
+    /// }
+    /// ```
+    pub fn walk_parallel<P, F>(&self, root: P, workers: usize, visitor: F) -> Result<()>
+    where
+        P: AsRef<str>,
+        F: FnMut(DirEntry),
+    {
+        let root = normalize_url(root.as_ref())?.into_owned();
+        let mut clients = Vec::new();
+        for _ in 0..cmp::max(workers, 1) {
+            clients.push(self.try_clone()?);
+        }
+        trace!(target: "smbc", "walking {} with {} workers", root, clients.len());
+        walk_with_workers(
+            clients,
+            root,
+            |client: &SmbClient, dir: &str| client.read_dir_plus(dir)?.collect(),
+            visitor,
+        )
+    }
+
+    /// Copy `len` bytes at offset `start` of SMB file `from` to the same offset of local file `to`
+    fn download_range(&self, from: &str, to: &Path, start: u64, len: u64) -> Result<u64> {
+        let mut src = self.open_ro(from)?;
+        src.seek(SeekFrom::Start(start))?;
+        let mut dst = at_local_path(to, fs::OpenOptions::new().write(true).open(to))?;
+        dst.seek(SeekFrom::Start(start))?;
+        stream_copy(
+            &mut Read::by_ref(&mut src).take(len),
+            &mut dst,
+            self.chunk_size,
+        )
+    }
+} // 2}}}
+  // 1}}}
+
+// SmbClientBuilder {{{1
+impl SmbClientBuilder {
+    // {{{2
+    /// Workgroup (domain) of `guest` credential used if auth function panics
+    /// (`WORKGROUP` by default).
+    pub fn default_workgroup(mut self, workgroup: &str) -> Self {
+        self.default_workgroup = workgroup.to_owned();
+        self
+    }
+
+    /// Use single connection to server for all its shares (enabled by default).
+    ///
+    /// Sharing connection saves on connection setup and authentication,
+    /// but requests to different shares are multiplexed over it.
+    /// Disable it to get separate connection per share, e.g. when
+    /// uploading to several shares on the same host concurrently.
+    pub fn one_share_per_server(mut self, one_share_per_server: bool) -> Self {
+        self.one_share_per_server = one_share_per_server;
+        self
+    }
+
+    /// Authenticate using Kerberos credential cache.
+    ///
+    /// Auth function is still used as fallback if Kerberos fails,
+    /// so it may return empty credentials.
+    pub fn use_kerberos(mut self, use_kerberos: bool) -> Self {
+        self.use_kerberos = use_kerberos;
+        self
+    }
+
+    /// Set `libsmbclient` debug level (`0` by default, i.e. only errors).
+    ///
+    /// Values above `10` (most verbose level) are clamped.
+    pub fn debug_level(mut self, level: u32) -> Self {
+        self.debug_level = level;
+        self
+    }
+
+    /// Write `libsmbclient` debug output to stderr instead of stdout
+    /// (disabled by default).
+    pub fn debug_to_stderr(mut self, debug_to_stderr: bool) -> Self {
+        self.debug_to_stderr = debug_to_stderr;
+        self
+    }
+
+    /// Pass `libsmbclient` debug output to `log` crate with target `smbc::native`
+    /// (disabled by default).
+    ///
+    /// Requires Samba 4.9+, older `libsmbclient` writes debug output
+    /// to stderr instead.
+    ///
+    /// Levels up to `3` are logged with `debug!`, more verbose ones with `trace!`,
+    /// use [`debug_level`](struct.SmbClientBuilder.html#method.debug_level)
+    /// to choose how much is produced.
+    pub fn debug_to_log(mut self, debug_to_log: bool) -> Self {
+        self.debug_to_log = debug_to_log;
+        self
+    }
+
+    /// Lowest protocol version client will negotiate
+    /// (`client min protocol` in `smb.conf`).
+    pub fn min_protocol(mut self, protocol: SmbProtocol) -> Self {
+        self.min_protocol = Some(protocol);
+        self
+    }
+
+    /// Highest protocol version client will negotiate
+    /// (`client max protocol` in `smb.conf`).
+    pub fn max_protocol(mut self, protocol: SmbProtocol) -> Self {
+        self.max_protocol = Some(protocol);
+        self
+    }
+
+    /// Set session encryption level.
+    ///
+    /// With [`EncryptionLevel::Require`](enum.EncryptionLevel.html#variant.Require)
+    /// operations on server which can't encrypt fail instead of
+    /// sending data in clear.
+    pub fn encryption_level(mut self, level: EncryptionLevel) -> Self {
+        self.encryption_level = Some(level);
+        self
+    }
+
+    /// Set default share mode of opened files, see
+    /// [`OpenOptions::share_mode`](struct.OpenOptions.html#method.share_mode).
+    pub fn share_mode(mut self, mode: ShareMode) -> Self {
+        self.share_mode = Some(mode);
+        self
+    }
+
+    /// Set timeout for connecting to server and for each following operation,
+    /// so request to unreachable or hung server fails instead of blocking forever.
+    ///
+    /// Timeout is rounded down to milliseconds and clamped to `c_int` range.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Treat file names as case sensitive when share doesn't tell
+    /// if its file system is (disabled by default).
+    ///
+    /// It affects only how client treats names, not server's own semantics,
+    /// and is used only if client can't get file system attributes of share
+    /// (e.g. old servers). Enable it for Samba shares with
+    /// `case sensitive = yes` so exact-case paths resolve reliably.
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = Some(case_sensitive);
+        self
+    }
+
+    /// Use full time names (`CREATE_TIME`, `ACCESS_TIME`, `WRITE_TIME`, `CHANGE_TIME`)
+    /// instead of `C_TIME`, `A_TIME`, `M_TIME` in `system.dos_attr.*` attributes
+    /// (disabled by default).
+    ///
+    /// Only names are affected, timestamps themselves are the same either way.
+    pub fn full_time_names(mut self, full_time_names: bool) -> Self {
+        self.full_time_names = Some(full_time_names);
+        self
+    }
+
+    /// Size of single read or write done by bulk helpers like
+    /// [`SmbClient::copy(..)`](struct.SmbClient.html#method.copy),
+    /// [`download_to(..)`](struct.SmbClient.html#method.download_to) or
+    /// [`SmbFile::copy_to(..)`](struct.SmbFile.html#method.copy_to)
+    /// (1 MiB by default, `0` is treated as `1`).
+    ///
+    /// `libsmbclient` doesn't expose maximum read and write sizes negotiated
+    /// with server, but it splits larger requests into negotiated ones
+    /// and pipelines them, so larger chunks (several MiB) help to saturate
+    /// fast links at cost of memory.
+    pub fn io_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = cmp::max(size, 1);
+        self
+    }
+
+    /// Creates new [`SmbClient`](struct.SmbClient.html) given auth function.
+    ///
+    /// Returns `Error::NewContext` or `Error::InitContext` if context
+    /// can't be created or configured and initialized respectively.
+    ///
+    /// Fails if [`min_protocol`](struct.SmbClientBuilder.html#method.min_protocol)
+    /// is above [`max_protocol`](struct.SmbClientBuilder.html#method.max_protocol),
+    /// or if any of them is set and `libsmbclient` is older than Samba 4.3.
+    ///
+    /// See [`SmbClient::new(..)`](struct.SmbClient.html#method.new).
+    pub fn build<'a, F>(self, auth_fn: &'a F) -> Result<SmbClient<'a>>
+    where
+        F: for<'b> Fn(&'b str, &'b str) -> (Cow<'a, str>, Cow<'a, str>, Cow<'a, str>) + Sync,
+    {
+        self.build_with_auth(
+            Arc::new(move |srv: &str, shr: &str| Ok(auth_fn(srv, shr))),
+            false,
+        )
+    }
+
+    /// Creates new [`SmbClient`](struct.SmbClient.html) given auth function
+    /// which may fail.
+    ///
+    /// When `auth_fn` returns `Err` operation which needed authentication
+    /// fails with `Error::AuthFailed` carrying error message,
+    /// e.g. to stop when no credentials are known for server.
+    /// Such client never falls back to anonymous login, so later calls
+    /// to the same server fail too instead of running as anonymous:
+    ///
+    /// ```rust
+    /// # use std::borrow::Cow;
+    /// #
+    /// # fn main() {}
+    /// #
+    /// # fn example() -> smbc::Result<()> {
+    ///     let auth = |host: &str, _share: &str| {
+    ///         if host == "fileserver" {
+    ///             Ok((Cow::Borrowed("CORP"), Cow::Borrowed("test"), Cow::Borrowed("Pa$$w0rd1")))
+    ///         } else {
+    ///             Err(format!("no credentials for {}", host))
+    ///         }
+    ///     };
+    ///     let client = smbc::SmbClientBuilder::default().build_with_fallible_auth(&auth)?;
+    ///     match client.open("smb://other/share/file") {
+    ///         Err(smbc::Error::AuthFailed(msg)) => println!("{}", msg),
+    ///         _ => (),
+    ///     }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    ///
+    /// See [`build(..)`](struct.SmbClientBuilder.html#method.build).
+    pub fn build_with_fallible_auth<'a, F, E>(self, auth_fn: &'a F) -> Result<SmbClient<'a>>
+    where
+        F: for<'b> Fn(
+                &'b str,
+                &'b str,
+            )
+                -> ::std::result::Result<(Cow<'a, str>, Cow<'a, str>, Cow<'a, str>), E>
+            + Sync,
+        E: fmt::Display,
+    {
+        let auth_fn =
+            Arc::new(move |srv: &str, shr: &str| auth_fn(srv, shr).map_err(|err| err.to_string()));
+        self.build_with_auth(auth_fn, true)
+    }
+
+    fn build_with_auth<'a>(
+        self,
+        auth_fn: Arc<AuthFn<'a>>,
+        fallible: bool,
+    ) -> Result<SmbClient<'a>> {
+        if let (Some(min), Some(max)) = (self.min_protocol, self.max_protocol) {
+            if min > max {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "min protocol is above max protocol",
+                )
+                .into());
+            }
+        }
+        let min_protocol = self.min_protocol.map(SmbProtocol::to_cstring);
+        let max_protocol = self.max_protocol.map(SmbProtocol::to_cstring);
+
+        unsafe {
+            let ctx = result_from_ptr_mut(smbc_new_context()).map_err(Error::NewContext)?;
+
+            let auth = Box::new(AuthData {
+                auth_fn,
+                failure: Mutex::new(None),
+                fallible,
+                default_workgroup: self.default_workgroup.clone(),
+            });
+            smbc_setOptionUserData(ctx, &*auth as *const AuthData as *mut c_void);
+            smbc_setFunctionAuthDataWithContext(ctx, Some(SmbClient::auth_wrapper));
+            if fallible {
+                // otherwise server is silently retried anonymously after auth fails
+                smbc_setOptionNoAutoAnonymousLogin(ctx, SMBC_TRUE);
+            }
+
+            smbc_setOptionOneSharePerServer(ctx, to_smbc_bool(self.one_share_per_server));
+
+            smbc_setDebug(ctx, cmp::min(self.debug_level, MAX_DEBUG_LEVEL) as c_int);
+            smbc_setOptionDebugToStderr(ctx, to_smbc_bool(self.debug_to_stderr));
+            if self.debug_to_log {
+                match ffi::smbc_setLogCallback() {
+                    Some(set_log_callback) => {
+                        set_log_callback(ctx, ptr::null_mut(), Some(log_native))
+                    }
+                    None => {
+                        warn!(target: "smbc", "libsmbclient can't log via callback, using stderr");
+                        smbc_setOptionDebugToStderr(ctx, SMBC_TRUE);
+                    }
+                }
+            }
+
+            if self.use_kerberos {
+                smbc_setOptionUseKerberos(ctx, SMBC_TRUE);
+                smbc_setOptionFallbackAfterKerberos(ctx, SMBC_TRUE);
+            }
+
+            if let Some(timeout) = self.timeout {
+                smbc_setTimeout(ctx, to_millis(timeout));
+            }
+
+            if let Some(case_sensitive) = self.case_sensitive {
+                smbc_setOptionCaseSensitive(ctx, to_smbc_bool(case_sensitive));
+            }
+
+            if let Some(full_time_names) = self.full_time_names {
+                smbc_setOptionFullTimeNames(ctx, to_smbc_bool(full_time_names));
+            }
+
+            if let Some(mode) = self.share_mode {
+                smbc_setOptionOpenShareMode(ctx, mode.to_native());
+            }
+
+            if let Some(level) = self.encryption_level {
+                smbc_setOptionSmbEncryptionLevel(ctx, level.to_native());
+            }
+
+            if min_protocol.is_some() || max_protocol.is_some() {
+                let as_ptr = |p: &Option<CString>| p.as_ref().map_or(ptr::null(), |p| p.as_ptr());
+                let res = match ffi::smbc_setOptionProtocols() {
+                    Some(set_protocols) => {
+                        match set_protocols(ctx, as_ptr(&min_protocol), as_ptr(&max_protocol)) {
+                            SMBC_TRUE => Ok(()),
+                            _ => Err(libc::EINVAL),
+                        }
+                    }
+                    // Samba before 4.3 can't limit protocols per context
+                    None => Err(libc::EOPNOTSUPP),
+                };
+                if let Err(errno) = res {
+                    smbc_free_context(ctx, 0);
+                    let err = io::Error::from_raw_os_error(errno);
+                    return Err(Error::InitContext(err));
+                }
+            }
+
+            // context isn't freed by failed init
+            if let Err(err) = result_from_ptr_mut(smbc_init_context(ctx)) {
+                smbc_free_context(ctx, 0);
+                return Err(Error::InitContext(err));
+            }
+
+            trace!(target: "smbc", "new smbclient");
+            Ok(SmbClient {
+                ctx,
+                auth,
+                chunk_size: self.chunk_size,
+                config: self,
+            })
+        }
+    }
+} // 2}}}
+
+impl Default for SmbClientBuilder {
+    fn default() -> SmbClientBuilder {
+        SmbClientBuilder {
+            default_workgroup: DEF_WORKGROUP.to_owned(),
+            one_share_per_server: true,
+            use_kerberos: false,
+            debug_level: 0,
+            debug_to_stderr: false,
+            debug_to_log: false,
+            min_protocol: None,
+            max_protocol: None,
+            encryption_level: None,
+            share_mode: None,
+            timeout: None,
+            case_sensitive: None,
+            full_time_names: None,
+            chunk_size: CHUNK_SIZE,
+        }
+    }
+}
+
+impl SmbProtocol {
+    // {{{2
+    fn to_cstring(self) -> CString {
+        let name = match self {
+            SmbProtocol::NT1 => "NT1",
+            SmbProtocol::SMB2 => "SMB2",
+            SmbProtocol::SMB3 => "SMB3",
+        };
+        CString::new(name).unwrap()
+    }
+} // 2}}}
+
+impl EncryptionLevel {
+    // {{{2
+    fn to_native(self) -> smbc_smb_encrypt_level {
+        match self {
+            EncryptionLevel::None => SMBC_ENCRYPTLEVEL_NONE,
+            EncryptionLevel::Request => SMBC_ENCRYPTLEVEL_REQUEST,
+            EncryptionLevel::Require => SMBC_ENCRYPTLEVEL_REQUIRE,
+        }
+    }
+} // 2}}}
+
+impl ShareMode {
+    // {{{2
+    fn to_native(self) -> smbc_share_mode {
+        match self {
+            ShareMode::DenyNone => SMBC_SHAREMODE_DENY_NONE,
+            ShareMode::DenyRead => SMBC_SHAREMODE_DENY_READ,
+            ShareMode::DenyWrite => SMBC_SHAREMODE_DENY_WRITE,
+            ShareMode::DenyAll => SMBC_SHAREMODE_DENY_ALL,
+        }
+    }
+} // 2}}}
+  // 1}}}
+
+// Metadata {{{1
+/// `time_t` with nanoseconds as `SystemTime`
+fn to_system_time(secs: libc::time_t, nsecs: i64) -> SystemTime {
+    let nsecs = Duration::new(0, nsecs as u32);
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64) + nsecs
+    } else {
+        UNIX_EPOCH - Duration::from_secs(-secs as u64) + nsecs
+    }
+}
+
+/// `SystemTime` as `timeval` (microsecond precision)
+fn to_timeval(time: SystemTime) -> libc::timeval {
+    let (secs, usecs) = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() as i64, d.subsec_micros() as i64),
+        Err(err) => {
+            // before epoch: negative seconds with non-negative microseconds
+            let d = err.duration();
+            match d.subsec_micros() {
+                0 => (-(d.as_secs() as i64), 0),
+                us => (-(d.as_secs() as i64) - 1, 1_000_000 - us as i64),
+            }
+        }
+    };
+    libc::timeval {
+        tv_sec: secs as libc::time_t,
+        tv_usec: usecs as _,
+    }
+}
+
+impl Metadata {
+    // {{{2
+    #[allow(clippy::unnecessary_cast)] // `st_*time_nsec` type differs across platforms
+    fn from_stat(st: libc::stat) -> Metadata {
+        Metadata {
+            size: st.st_size as u64,
+            mode: st.st_mode,
+            accessed: to_system_time(st.st_atime, st.st_atime_nsec as i64),
+            modified: to_system_time(st.st_mtime, st.st_mtime_nsec as i64),
+            changed: to_system_time(st.st_ctime, st.st_ctime_nsec as i64),
+        }
+    }
+
+    /// Metadata from `readdirplus` entry with mode mapped
+    /// from DOS attributes the same way `libsmbclient` does for `stat`.
+    #[allow(clippy::unnecessary_cast)] // `timespec` field types differ across platforms
+    fn from_file_info(info: &libsmb_file_info) -> Metadata {
+        let attrs = DosAttributes(u32::from(info.attrs));
+        let mut mode = if attrs.contains(DosAttributes::DIRECTORY) {
+            libc::S_IFDIR | 0o555
+        } else {
+            libc::S_IFREG | 0o444
+        };
+        if attrs.contains(DosAttributes::ARCHIVE) {
+            mode |= 0o100;
+        }
+        if attrs.contains(DosAttributes::SYSTEM) {
+            mode |= 0o010;
+        }
+        if attrs.contains(DosAttributes::HIDDEN) {
+            mode |= 0o001;
+        }
+        if !attrs.contains(DosAttributes::READONLY) {
+            mode |= 0o200;
+        }
+        let time = |ts: &libc::timespec| to_system_time(ts.tv_sec, ts.tv_nsec as i64);
+        Metadata {
+            size: info.size,
+            mode,
+            accessed: time(&info.atime_ts),
+            modified: time(&info.mtime_ts),
+            changed: time(&info.ctime_ts),
+        }
+    }
+
+    /// Size of file in bytes.
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    /// Check if file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Type of entry.
+    pub fn file_type(&self) -> FileType {
+        match self.mode & libc::S_IFMT {
+            libc::S_IFREG => FileType::File,
+            libc::S_IFDIR => FileType::Dir,
+            libc::S_IFLNK => FileType::Symlink,
+            _ => FileType::Other,
+        }
+    }
+
+    /// Check if entry is directory.
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == FileType::Dir
+    }
+
+    /// Check if entry is regular file.
+    pub fn is_file(&self) -> bool {
+        self.file_type() == FileType::File
+    }
+
+    /// Permissions of entry.
+    pub fn permissions(&self) -> Permissions {
+        Permissions::from_mode(self.mode)
+    }
+
+    /// Last access time (UTC).
+    pub fn accessed(&self) -> SystemTime {
+        self.accessed
+    }
+
+    /// Last modification time (UTC).
+    pub fn modified(&self) -> SystemTime {
+        self.modified
+    }
+
+    /// Last status change time (UTC).
+    pub fn changed(&self) -> SystemTime {
+        self.changed
+    }
+} // 2}}}
+  // 1}}}
+
+// Permissions {{{1
+impl Permissions {
+    // {{{2
+    /// Permissions from POSIX `mode` (file type bits are ignored).
+    pub fn from_mode(mode: mode_t) -> Permissions {
+        Permissions {
+            mode: mode & 0o7777,
+        }
+    }
+
+    /// POSIX permission bits (e.g. `0o644`).
+    pub fn mode(&self) -> mode_t {
+        self.mode
+    }
+
+    /// Check if no one can write to entry.
+    pub fn readonly(&self) -> bool {
+        self.mode & 0o222 == 0
+    }
+
+    /// Unset all write bits or set owner's one.
+    pub fn set_readonly(&mut self, readonly: bool) {
+        if readonly {
+            self.mode &= !0o222;
+        } else {
+            self.mode |= 0o200;
+        }
+    }
+} // 2}}}
+  // 1}}}
+
+// ShareInfo {{{1
+impl ShareInfo {
+    // {{{2
+    /// Share name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Share type.
+    pub fn kind(&self) -> ShareKind {
+        self.kind
+    }
+
+    /// Share comment (description set by administrator), may be empty.
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+
+    /// Hidden (administrative) shares have names ending with `$`.
+    pub fn is_hidden(&self) -> bool {
+        self.name.ends_with('$')
+    }
+} // 2}}}
+  // 1}}}
+
+// FsStats {{{1
+impl FsStats {
+    // {{{2
+    #[allow(clippy::unnecessary_cast)] // `statvfs` field types differ across platforms
+    fn from_statvfs(st: &ffi::statvfs) -> FsStats {
+        let block_size = st.f_bsize as u64;
+        // block counts are in fragments, some servers leave fragment size unset
+        let fragment_size = match st.f_frsize as u64 {
+            0 => block_size,
+            frsize => frsize,
+        };
+        FsStats {
+            block_size,
+            fragment_size,
+            blocks: st.f_blocks as u64,
+            blocks_free: st.f_bfree as u64,
+            blocks_available: st.f_bavail as u64,
+            files: st.f_files as u64,
+            files_free: st.f_ffree as u64,
+            capabilities: FsCapabilities(st.f_flag as u32),
+        }
+    }
+
+    /// Preferred I/O block size in bytes.
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// Allocation unit in bytes, block counts are in these units.
+    pub fn fragment_size(&self) -> u64 {
+        self.fragment_size
+    }
+
+    /// Total number of allocation units.
+    pub fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    /// Number of free allocation units.
+    pub fn blocks_free(&self) -> u64 {
+        self.blocks_free
+    }
+
+    /// Number of allocation units available to current user (quota aware).
+    pub fn blocks_available(&self) -> u64 {
+        self.blocks_available
+    }
+
+    /// Total size of share in bytes.
+    pub fn total_space(&self) -> u64 {
+        self.blocks.saturating_mul(self.fragment_size)
+    }
+
+    /// Free space in bytes.
+    pub fn free_space(&self) -> u64 {
+        self.blocks_free.saturating_mul(self.fragment_size)
+    }
+
+    /// Space available to current user in bytes, check it before uploading.
+    pub fn available_space(&self) -> u64 {
+        self.blocks_available.saturating_mul(self.fragment_size)
+    }
+
+    /// Total number of file nodes (zero if server doesn't report it).
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// Number of free file nodes (zero if server doesn't report it).
+    pub fn files_free(&self) -> u64 {
+        self.files_free
+    }
+
+    /// Capabilities of share.
+    pub fn capabilities(&self) -> FsCapabilities {
+        self.capabilities
+    }
+} // 2}}}
+  // 1}}}
+
+// FsCapabilities {{{1
+impl FsCapabilities {
+    // {{{2
+    /// Share is read-only.
+    pub fn read_only(&self) -> bool {
+        self.0 & SMBC_VFS_FEATURE_RDONLY != 0
+    }
+
+    /// Share is part of DFS namespace.
+    pub fn dfs(&self) -> bool {
+        self.0 & SMBC_VFS_FEATURE_DFS != 0
+    }
+
+    /// File names are case sensitive.
+    pub fn case_sensitive(&self) -> bool {
+        self.0 & SMBC_VFS_FEATURE_CASE_INSENSITIVE == 0
+    }
+
+    /// Server supports CIFS Unix extensions (POSIX permissions, symlinks).
+    pub fn unix_extensions(&self) -> bool {
+        self.0 & SMBC_VFS_FEATURE_NO_UNIXCIFS == 0
+    }
+
+    /// Raw `SMBC_VFS_FEATURE_*` bits.
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+} // 2}}}
+  // 1}}}
+
+// WorkgroupInfo {{{1
+impl WorkgroupInfo {
+    // {{{2
+    /// Workgroup name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Comment, usually name of master browser.
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+} // 2}}}
+  // 1}}}
+
+// ServerInfo {{{1
+impl ServerInfo {
+    // {{{2
+    /// Server (NetBIOS) name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Server comment (description), may be empty.
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+} // 2}}}
+  // 1}}}
+
+// PrintJobInfo {{{1
+impl PrintJobInfo {
+    // {{{2
+    /// Job id in printer queue.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Job priority (lower is higher).
+    pub fn priority(&self) -> u16 {
+        self.priority
+    }
+
+    /// Size of spooled data in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// User owning job.
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// Job (document) name, empty for jobs opened without name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Time job was spooled (UTC).
+    pub fn spooled(&self) -> SystemTime {
+        self.spooled
+    }
+} // 2}}}
+  // 1}}}
+
+// OpenOptions {{{1
+/// Describes options for opening file:
+///
+/// * `read` if readable;
+/// * `write` if writable;
+/// * `flags` is *bitwise OR* of `O_CREAT`, `O_EXCL` and `O_TRUNC`;
+/// * `mode` for *POSIX* file mode.
+#[derive(Clone, Copy, Debug)]
+pub struct OpenOptions {
+    flags: c_int,
+    read: bool,
+    write: bool,
+    create_new: bool,
+    custom_flags: c_int,
+    share_mode: Option<ShareMode>,
+    mode: mode_t,
+}
+
+impl OpenOptions {
+    // {{{2
+    /// Allows reading file (set by default).
+    pub fn read(mut self, read: bool) -> Self {
+        self.read = read;
+        self
+    }
+
+    /// Allows writing to file.
+    pub fn write(mut self, write: bool) -> Self {
+        self.write = write;
+        self
+    }
+
+    /// Allows appending to file, implies
+    /// [`write`](struct.OpenOptions.html#method.write).
+    ///
+    /// `libsmbclient` moves to end of file only when it's opened,
+    /// so [`SmbFile`](struct.SmbFile.html) seeks to end before each write.
+    pub fn append(mut self, append: bool) -> Self {
+        self.flag(libc::O_APPEND, append);
+        self
+    }
+
+    /// Allows creating file if it doesn't exists.
+    ///
+    /// Opening file will fail in case file exists if
+    /// [`exclusive`](struct.OpenOptions.html#method.exclusive)
+    /// also set.
+    pub fn create(mut self, create: bool) -> Self {
+        self.flag(libc::O_CREAT, create);
+        self
+    }
+
+    /// File will be truncated (size set to `0`)
+    /// if it's already exists.
+    ///
+    /// Requires [`write`](struct.OpenOptions.html#method.write) and can't be
+    /// combined with [`append`](struct.OpenOptions.html#method.append).
+    pub fn truncate(mut self, truncate: bool) -> Self {
+        self.flag(libc::O_TRUNC, truncate);
+        self
+    }
+
+    /// Always create new file failing if it already exists,
+    /// like `std::fs::OpenOptions::create_new`.
+    ///
+    /// Same as `O_CREAT | O_EXCL`, requires
+    /// [`write`](struct.OpenOptions.html#method.write).
+    /// If set [`create`](struct.OpenOptions.html#method.create) and
+    /// [`truncate`](struct.OpenOptions.html#method.truncate) are ignored.
+    pub fn create_new(mut self, create_new: bool) -> Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// `open_*` will fail if file already exists
+    /// (when used with `create` also set).
+    ///
+    /// See [`create`](struct.OpenOptions.html#method.create)
+    pub fn exclusive(mut self, exclusive: bool) -> Self {
+        self.flag(libc::O_EXCL, exclusive);
+        self
+    }
+
+    /// Open file in non-blocking mode (`O_NONBLOCK`).
+    ///
+    /// Reads and writes which would block fail with
+    /// `io::ErrorKind::WouldBlock` (`EAGAIN`), so usual retry patterns work.
+    /// Not all Samba versions and transports honor it, so operations
+    /// may still block on underlying socket.
+    pub fn non_blocking(mut self, non_blocking: bool) -> Self {
+        self.flag(libc::O_NONBLOCK, non_blocking);
+        self
+    }
+
+    /// Pass extra `flags` to native `open` (e.g. `libc::O_SYNC`),
+    /// like `std::os::unix::fs::OpenOptionsExt::custom_flags`.
+    ///
+    /// Replaces flags set by previous call. Access mode bits are ignored,
+    /// use [`read`](struct.OpenOptions.html#method.read) and
+    /// [`write`](struct.OpenOptions.html#method.write) instead.
+    /// Whether flag has any effect depends on `libsmbclient` and server.
+    pub fn custom_flags(mut self, flags: c_int) -> Self {
+        self.custom_flags = flags & !(libc::O_RDONLY | libc::O_WRONLY | libc::O_RDWR);
+        self
+    }
+
+    /// Deny other opens of file while it's open, like Windows `CreateFile`
+    /// share flags (client default is used if not set).
+    ///
+    /// Open fails with sharing violation (`EBUSY`) if file is already open
+    /// in conflicting mode, e.g. by Windows client.
+    ///
+    /// ```rust
+    /// # use std::io::Write;
+    /// # fn main() {}
+    /// #
+    /// fn update(client: &smbc::SmbClient, contents: &[u8]) -> smbc::Result<()> {
+    ///     let options = smbc::OpenOptions::default()
+    ///         .write(true)
+    ///         .truncate(true)
+    ///         .share_mode(smbc::ShareMode::DenyAll);
+    ///     let mut file = client.open_with("smb://server/share/db.dat", options)?;
+    ///     file.write_all(contents)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn share_mode(mut self, mode: ShareMode) -> Self {
+        self.share_mode = Some(mode);
+        self
+    }
+
+    /// Set POSIX file mode
+    pub fn mode(mut self, mode: mode_t) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    fn flag(&mut self, flag: c_int, on: bool) {
+        if on {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    /// Flags for native `open`, rejects incompatible combinations
+    /// the same way `std::fs::OpenOptions` does with `Error::InvalidOpenOptions`.
+    fn to_flags(self) -> Result<c_int> {
+        let invalid = |msg: &str| Err(Error::InvalidOpenOptions(msg.to_owned()));
+        let mut flags = self.flags;
+        // appending is writing too
+        let write = self.write || flags & libc::O_APPEND != 0;
+        if self.create_new {
+            if !write {
+                return invalid("create_new requires write or append access");
+            }
+            // new file is empty anyway
+            flags = (flags & !libc::O_TRUNC) | libc::O_CREAT | libc::O_EXCL;
+        }
+        let has = |flag| flags & flag != 0;
+        if has(libc::O_TRUNC) && !self.write {
+            return invalid("truncate requires write access");
+        }
+        if has(libc::O_APPEND) && has(libc::O_TRUNC) {
+            return invalid("append and truncate are mutually exclusive");
+        }
+        if has(libc::O_CREAT) && !write {
+            return invalid("create requires write or append access");
+        }
+        if has(libc::O_EXCL) && !has(libc::O_CREAT) {
+            return invalid("exclusive requires create");
+        }
+
+        let base_mode = match (self.read, write) {
+            // defaults to read only
+            (false, false) | (true, false) => libc::O_RDONLY,
+            (false, true) => libc::O_WRONLY,
+            (true, true) => libc::O_RDWR,
+        };
+        Ok(base_mode | flags | self.custom_flags)
+    }
+} // }}}
+  // 1}}}
+
+impl Default for OpenOptions {
+    /// Default [`OpenOptions`](struct.OpenOptions.html) is
+    /// read-only with POSIX perms `0644`
+    /// (`rw` for owner, `r` for group and others).
+    fn default() -> OpenOptions {
+        OpenOptions {
+            flags: 0,
+            read: true,
+            write: false,
+            create_new: false,
+            custom_flags: 0,
+            share_mode: None,
+            mode: 0o644,
+        }
+    }
+}
+
+// TransferOptions {{{1
+/// Describes how file is transferred between local filesystem and SMB share
+/// by [`download_to(..)`](struct.SmbClient.html#method.download_to) and
+/// [`upload_from(..)`](struct.SmbClient.html#method.upload_from).
+///
+/// By default only content is copied at full speed and parent directory should exist.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TransferOptions {
+    create_parents: bool,
+    preserve_mode: bool,
+    preserve_mtime: bool,
+    max_rate: Option<u64>,
+    resume: bool,
+    verify_tail: u64,
+}
+
+impl TransferOptions {
+    // {{{2
+    /// Create missing parent directories of destination.
+    pub fn create_parents(mut self, create_parents: bool) -> Self {
+        self.create_parents = create_parents;
+        self
+    }
+
+    /// Copy POSIX mode of source to destination.
+    ///
+    /// Servers without UNIX extensions map only write permission
+    /// to read-only attribute.
+    pub fn preserve_mode(mut self, preserve_mode: bool) -> Self {
+        self.preserve_mode = preserve_mode;
+        self
+    }
+
+    /// Copy access and modification times of source to destination
+    /// (with second precision).
+    pub fn preserve_mtime(mut self, preserve_mtime: bool) -> Self {
+        self.preserve_mtime = preserve_mtime;
+        self
+    }
+
+    /// Limit transfer rate to `bytes_per_sec` on average, `0` means no limit.
+    ///
+    /// Helps not to starve share for other users, e.g. when backup runs
+    /// during business hours. Limit applies to each file, trees and syncs
+    /// copy files one by one so it limits them as well.
+    pub fn max_rate(mut self, bytes_per_sec: u64) -> Self {
+        self.max_rate = if bytes_per_sec > 0 {
+            Some(bytes_per_sec)
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Continue partial destination left by interrupted transfer
+    /// instead of copying whole file again.
+    ///
+    /// Destination which isn't longer than source is assumed to be its prefix,
+    /// see [`verify_tail`](struct.TransferOptions.html#method.verify_tail),
+    /// otherwise file is copied from the start. Returned byte counts include
+    /// only bytes transferred now, progress includes resumed part too.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Compare last `bytes` of partial destination with source
+    /// before resuming (`0` by default, i.e. only length is checked).
+    ///
+    /// Catches destination left by other version of source
+    /// at cost of reading `bytes` from both sides.
+    pub fn verify_tail(mut self, bytes: u64) -> Self {
+        self.verify_tail = bytes;
+        self
+    }
+} // }}}
+  // 1}}}
+
+// XattrFlags {{{1
+/// Describes how [`setxattr`](struct.SmbClient.html#method.setxattr)
+/// treats existing attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XattrFlags {
+    /// Create or replace attribute as needed.
+    None,
+    /// Fail with `EEXIST` if attribute already exists.
+    Create,
+    /// Fail with `ENODATA` if attribute doesn't exist.
+    Replace,
+}
+
+impl XattrFlags {
+    // {{{2
+    fn to_flags(self) -> c_int {
+        match self {
+            XattrFlags::None => 0,
+            XattrFlags::Create => SMBC_XATTR_FLAG_CREATE,
+            XattrFlags::Replace => SMBC_XATTR_FLAG_REPLACE,
+        }
+    }
+} // 2}}}
+  // 1}}}
+
+// SmbFile {{{1
+impl<'a, 'b> SmbFile<'a, 'b> {
+    // {{{2
+    /// Current position in file.
+    ///
+    /// Same as `seek(SeekFrom::Current(0))` but doesn't need `Seek` in scope.
+    pub fn tell(&mut self) -> Result<u64> {
+        self.position()
+    }
+
+    /// SMB path file was opened with.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Opens file again read-only returning new `SmbFile`
+    /// positioned at the same offset as this one.
+    ///
+    /// `libsmbclient` can't duplicate handles, so clone is an independent
+    /// handle opened by path on the same client: seeking or reading it
+    /// doesn't move this file's position and vice versa.
+    pub fn try_clone(&self) -> Result<SmbFile<'a, 'b>> {
+        let pos = self.position()?;
+        let mut clone = self.smbc.open_ro(&self.path)?;
+        if pos > 0 {
+            clone.seek(SeekFrom::Start(pos))?;
+        }
+        Ok(clone)
+    }
+
+    /// Truncate or extend file to `size` bytes, like `std::fs::File::set_len`.
+    ///
+    /// Extended part is filled with zeros, file position isn't changed.
+    pub fn set_len(&mut self, size: u64) -> Result<()> {
+        let ftruncate_fn = self.smbc.get_fn(smbc_getFunctionFtruncate)?;
+        trace!(target: "smbc", "truncating {} to {}", self.path, size);
+        self.smbc.check(to_result_with_le(ftruncate_fn(
+            self.smbc.ctx,
+            self.fd,
+            size as off_t,
+        )))?;
+        Ok(())
+    }
+
+    fn position(&self) -> Result<u64> {
+        Ok(self.lseek(SeekFrom::Current(0))?)
+    }
+
+    /// Copy rest of file to `dst` returning number of bytes copied.
+    ///
+    /// Same as `io::copy(file, dst)` but reads in chunks of
+    /// [`io_chunk_size`](struct.SmbClientBuilder.html#method.io_chunk_size)
+    /// instead of 8 KiB used by `io::copy`.
+    ///
+    /// ```rust
+    /// # use std::io;
+    /// # fn main() {}
+    /// #
+    /// fn cat(client: &smbc::SmbClient, path: &str) -> smbc::Result<u64> {
+    ///     let stdout = io::stdout();
+    ///     let mut stdout = stdout.lock();
+    ///     client.open(path)?.copy_to(&mut stdout)
+    /// }
+    /// ```
+    pub fn copy_to<W: Write>(&mut self, dst: &mut W) -> Result<u64> {
+        let chunk_size = self.smbc.chunk_size;
+        stream_copy(self, dst, chunk_size)
+    }
+
+    /// Copy rest of `src` to file returning number of bytes copied.
+    ///
+    /// Same as `io::copy(src, file)` but writes in chunks of
+    /// [`io_chunk_size`](struct.SmbClientBuilder.html#method.io_chunk_size).
+    pub fn copy_from<R: Read>(&mut self, src: &mut R) -> Result<u64> {
+        let chunk_size = self.smbc.chunk_size;
+        stream_copy(src, self, chunk_size)
+    }
+
+    /// Read up to `max` bytes appending them to `buf`, returns number
+    /// of bytes read (`0` at end of file).
+    ///
+    /// Unlike `Read::read` into fresh buffer new space isn't zero-initialized
+    /// first, bytes are read directly into reserved capacity of `buf`
+    /// (stable equivalent of unstable `Read::read_buf`).
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn read_chunks(client: &smbc::SmbClient, path: &str) -> smbc::Result<Vec<u8>> {
+    ///     let mut file = client.open(path)?;
+    ///     let mut buf = Vec::new();
+    ///     while file.read_uninit(&mut buf, 4 * 1024 * 1024)? > 0 {}
+    ///     Ok(buf)
+    /// }
+    /// ```
+    pub fn read_uninit(&mut self, buf: &mut Vec<u8>, max: usize) -> io::Result<usize> {
+        buf.reserve(max);
+        let len = buf.len();
+        // native read only writes to buffer, it never reads uninitialized bytes
+        let bytes_read = unsafe { self.read_ptr(buf.as_mut_ptr().add(len), max)? };
+        unsafe { buf.set_len(len + bytes_read) };
+        Ok(bytes_read)
+    }
+
+    /// Get metadata of open file without looking it up by path again.
+    pub fn metadata(&self) -> Result<Metadata> {
+        self.fstat().map(Metadata::from_stat)
+    }
+
+    /// Get file system statistics and capabilities of share containing open file.
+    pub fn fstatvfs(&self) -> Result<FsStats> {
+        let fstatvfs_fn = self.smbc.get_fn(ffi::smbc_getFunctionFstatVFS)?;
+        let mut st: ffi::statvfs = unsafe { mem::zeroed() };
+        self.smbc.check(to_result_with_le(fstatvfs_fn(
+            self.smbc.ctx,
+            self.fd,
+            &mut st,
+        )))?;
+        Ok(FsStats::from_statvfs(&st))
+    }
+
+    /// Close file reporting error unlike `drop`.
+    fn close(self) -> Result<()> {
+        let close_fn = self.smbc.get_fn(smbc_getFunctionClose)?;
+        let res = self
+            .smbc
+            .check(to_result_with_le(close_fn(self.smbc.ctx, self.fd)));
+        // handle is closed even on error, so `Drop` mustn't close it again
+        let mut file = mem::ManuallyDrop::new(self);
+        drop(mem::take(&mut file.path));
+        res?;
+        Ok(())
+    }
+
+    fn fstat(&self) -> Result<libc::stat> {
+        let fstat_fn = self.smbc.get_fn(smbc_getFunctionFstat)?;
+        let mut st: libc::stat = unsafe { mem::zeroed() };
+        self.smbc
+            .check(to_result_with_le(fstat_fn(self.smbc.ctx, self.fd, &mut st)))?;
+        Ok(st)
+    }
+
+    /// Bytes left to read or `None` if it can't be found out.
+    fn remaining(&mut self) -> Option<usize> {
+        let size = self.fstat().ok()?.st_size as u64;
+        let pos = self.tell().ok()?;
+        match size.saturating_sub(pos) {
+            0 => None,
+            remaining => Some(remaining as usize),
+        }
+    }
+} // }}}
+
+impl<'a, 'b> Read for SmbFile<'a, 'b> {
+    // {{{2
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_raw(buf)
+    }
+
+    /// Reads into `bufs` with single native read through intermediate buffer
+    /// of up to [`io_chunk_size`](struct.SmbClientBuilder.html#method.io_chunk_size)
+    /// bytes, filling buffers in order.
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        let limit = match vectored_limit(bufs.iter().map(|b| b.len()), self.smbc.chunk_size) {
+            Some(limit) => limit,
+            None => return self.read_raw(first_nonempty_mut(bufs)),
+        };
+        let mut chunk = vec![0u8; limit];
+        let bytes_read = self.read_raw(&mut chunk)?;
+
+        let mut rest = &chunk[..bytes_read];
+        for buf in bufs {
+            if rest.is_empty() {
+                break;
+            }
+            let len = cmp::min(buf.len(), rest.len());
+            buf[..len].copy_from_slice(&rest[..len]);
+            rest = &rest[len..];
+        }
+        Ok(bytes_read)
+    }
+
+    /// Reserves space for the rest of file (as reported by `fstat`) up front,
+    /// so it's read in large chunks without reallocations
+    /// and without zero-initializing buffer (see
+    /// [`read_uninit(..)`](struct.SmbFile.html#method.read_uninit)).
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let start = buf.len();
+        if let Some(remaining) = self.remaining() {
+            trace!(target: "smbc", "reserving {} bytes to read file", remaining);
+            // one more byte to see EOF without growing buffer
+            buf.reserve(remaining + 1);
+        }
+        loop {
+            let max = match buf.capacity() - buf.len() {
+                0 => self.smbc.chunk_size,
+                spare => spare,
+            };
+            match self.read_uninit(buf, max) {
+                Ok(0) => return Ok(buf.len() - start),
+                Ok(_) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        let mut bytes = mem::take(buf).into_bytes();
+        let start = bytes.len();
+        let res = self.read_to_end(&mut bytes);
+        let res = match str::from_utf8(&bytes[start..]) {
+            Ok(_) => res,
+            Err(_) => {
+                bytes.truncate(start);
+                res.and(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "stream did not contain valid UTF-8",
+                )))
+            }
+        };
+        // only validated bytes are kept
+        *buf = unsafe { String::from_utf8_unchecked(bytes) };
+        res
+    }
+} // }}}
+
+impl<'a, 'b> Write for SmbFile<'a, 'b> {
+    // {{{2
+    /// Writes whole `buf` unless error occurs, re-issuing native write
+    /// if server accepted only part of it.
+    ///
+    /// If error occurs after some bytes were written their count is returned,
+    /// error will be reported by next write.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.append {
+            // server doesn't know about `O_APPEND`, end may have moved since open
+            self.seek(SeekFrom::End(0))?;
+        }
+        self.write_raw(buf)
+    }
+
+    /// Coalesces `bufs` into single native write of up to
+    /// [`io_chunk_size`](struct.SmbClientBuilder.html#method.io_chunk_size)
+    /// bytes instead of writing them one by one.
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        let limit = match vectored_limit(bufs.iter().map(|b| b.len()), self.smbc.chunk_size) {
+            Some(limit) => limit,
+            None => {
+                let first = bufs
+                    .iter()
+                    .find(|b| !b.is_empty())
+                    .map_or(&[][..], |b| &b[..]);
+                return self.write(first);
+            }
+        };
+        let mut chunk = Vec::with_capacity(limit);
+        for buf in bufs {
+            let len = cmp::min(buf.len(), limit - chunk.len());
+            chunk.extend_from_slice(&buf[..len]);
+            if chunk.len() == limit {
+                break;
+            }
+        }
+        self.write(&chunk)
+    }
+
+    /// Does nothing: `libsmbclient` doesn't buffer writes, each `write`
+    /// is sent to server before it returns. Wrap file in
+    /// [`SmbBufFile`](struct.SmbBufFile.html) to coalesce small writes.
+    ///
+    /// There is no native `fsync`, so data accepted by server may
+    /// still be in its cache. Dropping (closing) file is the strongest
+    /// durability point available, the rest is up to server caching settings.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+} // }}}
+
+impl<'a, 'b> Seek for SmbFile<'a, 'b> {
+    // {{{2
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.lseek(pos)
+    }
+} // }}}
+
+impl<'a, 'b> FileExt for SmbFile<'a, 'b> {
+    // {{{2
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.at_offset(offset, || self.read_raw(buf))
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.at_offset(offset, || self.write_raw(buf))
+    }
+} // }}}
+
+impl<'a, 'b> SmbFile<'a, 'b> {
+    // {{{2
+    fn read_raw(&self, buf: &mut [u8]) -> io::Result<usize> {
+        unsafe { self.read_ptr(buf.as_mut_ptr(), buf.len()) }
+    }
+
+    /// Read up to `len` bytes to possibly uninitialized memory at `ptr`
+    unsafe fn read_ptr(&self, ptr: *mut u8, len: usize) -> io::Result<usize> {
+        trace!(target: "smbc", "reading file to buf [{:?};{}]", ptr, len);
+        let read_fn = self.smbc.get_fn(smbc_getFunctionRead)?;
+        let bytes_read = self.smbc.check_io(to_result_with_le(read_fn(
+            self.smbc.ctx,
+            self.fd,
+            ptr as *mut c_void,
+            len as _,
+        )))?;
+        Ok(bytes_read as usize)
+    }
+
+    /// Writes whole `buf` at current position, see `Write::write`
+    fn write_raw(&self, buf: &[u8]) -> io::Result<usize> {
+        trace!(target: "smbc", "writing buf [{:?};{}] to file", buf.as_ptr(), buf.len());
+        let write_fn = self.smbc.get_fn(smbc_getFunctionWrite)?;
+        let mut written = 0;
+        while written < buf.len() {
+            let rest = &buf[written..];
+            let res = self.smbc.check_io(to_result_with_le(write_fn(
+                self.smbc.ctx,
+                self.fd,
+                rest.as_ptr() as *const c_void,
+                rest.len() as _,
+            )));
+            match res {
+                Ok(0) => break,
+                Ok(bytes_wrote) => written += bytes_wrote as usize,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) if written > 0 => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(written)
+    }
+
+    fn lseek(&self, pos: SeekFrom) -> io::Result<u64> {
+        trace!(target: "smbc", "seeking file {:?}", pos);
+        let lseek_fn = self.smbc.get_fn(smbc_getFunctionLseek)?;
+        let (whence, off) = match pos {
+            SeekFrom::Start(p) => (libc::SEEK_SET, p as off_t),
+            SeekFrom::End(p) => (libc::SEEK_END, p as off_t),
+            SeekFrom::Current(p) => (libc::SEEK_CUR, p as off_t),
+        };
+        let res = lseek_fn(self.smbc.ctx, self.fd, off, whence);
+        let res = self
+            .smbc
+            .check_io(to_result_with_errno(res, libc::EINVAL))?;
+        Ok(res as u64)
+    }
+
+    /// Run `f` at `offset` restoring current position after it
+    fn at_offset<T, F>(&self, offset: u64, f: F) -> io::Result<T>
+    where
+        F: FnOnce() -> io::Result<T>,
+    {
+        let pos = self.lseek(SeekFrom::Current(0))?;
+        self.lseek(SeekFrom::Start(offset))?;
+        let res = f();
+        let restored = self.lseek(SeekFrom::Start(pos));
+        let res = res?;
+        restored?;
+        Ok(res)
+    }
+} // }}}
+
+// Digest {{{1
+impl Digest for DefaultHasher {
+    type Output = u64;
+
+    fn update(&mut self, data: &[u8]) {
+        self.write(data);
+    }
+
+    fn finalize(self) -> u64 {
+        self.finish()
+    }
+}
+
+impl Digest for NoDigest {
+    type Output = ();
+
+    fn update(&mut self, _: &[u8]) {
+        match *self {}
+    }
+
+    fn finalize(self) {
+        match self {}
+    }
+}
+
+impl<'d, R: Read, D: Digest> Read for DigestRead<'d, R, D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(digest) = self.digest.as_mut() {
+            digest.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+// 1}}}
+
+// SmbPrintJob {{{1
+impl<'a, 'b> SmbPrintJob<'a, 'b> {
+    // {{{2
+    /// Close job submitting it for printing.
+    ///
+    /// Unlike dropping job, reports spooler errors returned on close.
+    pub fn finish(self) -> Result<()> {
+        trace!(target: "smbc", "finishing print job on {}", self.file.path);
+        self.file.close()
+    }
+} // 2}}}
+
+impl<'a, 'b> Write for SmbPrintJob<'a, 'b> {
+    // {{{2
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        self.file.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+} // 2}}}
+  // 1}}}
+
+impl<'a, 'b> Drop for SmbFile<'a, 'b> {
+    // {{{2
     fn drop(&mut self) {
-        trace!(target: "smbc", "closing smbclient");
-        unsafe {
-            smbc_free_context(self.ctx, 1 as c_int);
+        trace!(target: "smbc", "closing file");
+        if let Ok(close_fn) = self.smbc.get_fn(smbc_getFunctionClose) {
+            close_fn(self.smbc.ctx, self.fd);
+        }
+    }
+} // }}}
+  // 1}}}
+
+// SmbDirectory {{{1
+impl<'a, 'b> SmbDirectory<'a, 'b> {
+    // {{{2
+    /// Current position in listing to continue from later with
+    /// [`seek(..)`](struct.SmbDirectory.html#method.seek).
+    pub fn tell(&self) -> Result<DirOffset> {
+        let telldir_fn = self.smbc.get_fn(smbc_getFunctionTelldir)?;
+        let offset = self
+            .smbc
+            .check(to_result_with_le(telldir_fn(self.smbc.ctx, self.fd)))?;
+        Ok(DirOffset(offset))
+    }
+
+    /// Continue listing from `offset` returned by
+    /// [`tell()`](struct.SmbDirectory.html#method.tell) of this directory.
+    pub fn seek(&self, offset: DirOffset) -> Result<()> {
+        let lseekdir_fn = self.smbc.get_fn(smbc_getFunctionLseekdir)?;
+        trace!(target: "smbc", "seeking dir to {:?}", offset);
+        self.smbc.check(to_result_with_le(lseekdir_fn(
+            self.smbc.ctx,
+            self.fd,
+            offset.0,
+        )))?;
+        Ok(())
+    }
+
+    /// Restart listing from the first entry.
+    pub fn rewind(&self) -> Result<()> {
+        self.seek(DirOffset(0))
+    }
+
+    /// Watch directory for changes (requires Samba 4.7+).
+    ///
+    /// Fails with `Error::NotSupported` if installed `libsmbclient` is older.
+    /// Blocks delivering changes matching `filter` to `callback`
+    /// until it returns `false`. With `recursive` changes in
+    /// subdirectories are reported too, their names are relative
+    /// to watched directory.
+    ///
+    /// Panic in `callback` stops watching.
+    ///
+    /// ```rust
+    /// # use std::borrow::Cow;
+    /// #
+    /// # fn main() {}
+    /// #
+    /// # fn example() -> smbc::Result<()> {
+    /// # let auth = |host: &str, share: &str| {
+    /// #    (Cow::Borrowed("WORKGROUP"), Cow::Borrowed("test"), Cow::Borrowed("Pa$$w0rd1"))
+    /// # };
+    ///     let client = smbc::SmbClient::new(&auth)?;
+    ///     let dir = client.open_dir("smb://127.0.0.1/share/incoming")?;
+    ///     dir.watch(false, smbc::NotifyFilter::FILE_NAME, |event| {
+    ///         println!("{:?} {}", event.action(), event.filename());
+    ///         event.action() != smbc::ChangeAction::Removed
+    ///     })?;
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn watch<F>(&self, recursive: bool, filter: NotifyFilter, mut callback: F) -> Result<()>
+    where
+        F: FnMut(ChangeEvent) -> bool,
+    {
+        self.notify(recursive, filter, |events| {
+            events.into_iter().all(&mut callback)
+        })
+    }
+
+    /// Deliver batches of changes reported by server to `callback` until it returns `false`.
+    fn notify<F>(&self, recursive: bool, filter: NotifyFilter, callback: F) -> Result<()>
+    where
+        F: FnMut(Vec<ChangeEvent>) -> bool,
+    {
+        let get_notify_fn = ffi::smbc_getFunctionNotify().ok_or(Error::NotSupported)?;
+        let notify_fn = self.smbc.get_fn(get_notify_fn)?;
+        trace!(target: "smbc", "watching dir with filter {:?}", filter);
+        let mut state: NotifyState<F> = (callback, None);
+        let res = notify_fn(
+            self.smbc.ctx,
+            self.fd,
+            to_smbc_bool(recursive),
+            filter.0,
+            0,
+            Some(SmbDirectory::notify_wrapper::<F>),
+            &mut state as *mut NotifyState<F> as *mut c_void,
+        );
+        // panic can't unwind through C, so it's resumed once `libsmbclient` is done
+        if let Some(payload) = state.1 {
+            panic::resume_unwind(payload);
+        }
+        self.smbc.check(to_result_with_le(res))?;
+        Ok(())
+    }
+
+    /// Notify callback passed to `libsmbclient`, `private_data` points
+    /// to `NotifyState<F>`. Panic of callback stops notification
+    /// and is stored to be resumed by `notify`.
+    extern "C" fn notify_wrapper<F>(
+        actions: *const smbc_notify_callback_action,
+        num_actions: size_t,
+        private_data: *mut c_void,
+    ) -> c_int
+    where
+        F: FnMut(Vec<ChangeEvent>) -> bool,
+    {
+        let state = unsafe { &mut *(private_data as *mut NotifyState<F>) };
+        let actions = if actions.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(actions, num_actions as usize) }
+        };
+        let mut events = Vec::with_capacity(actions.len());
+        for action in actions {
+            match ChangeAction::from_native(action.action) {
+                Some(kind) => events.push(ChangeEvent {
+                    action: kind,
+                    filename: unsafe { cstr(action.filename) }.into_owned(),
+                }),
+                None => trace!(target: "smbc", "unknown notify action {}", action.action),
+            }
+        }
+        trace!(target: "smbc", "notify {:?}", events);
+        let callback = &mut state.0;
+        match panic::catch_unwind(panic::AssertUnwindSafe(|| callback(events))) {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(payload) => {
+                state.1 = Some(payload);
+                1
+            }
+        }
+    }
+} // }}}
+
+impl<'a, 'b> Drop for SmbDirectory<'a, 'b> {
+    // {{{2
+    fn drop(&mut self) {
+        trace!(target: "smbc", "closing dir");
+        if let Ok(closedir_fn) = self.smbc.get_fn(smbc_getFunctionClosedir) {
+            closedir_fn(self.smbc.ctx, self.fd);
+        }
+    }
+} // }}}
+  // 1}}}
+
+// DosAttributes {{{1
+impl DosAttributes {
+    // {{{2
+    /// Read-only
+    pub const READONLY: DosAttributes = DosAttributes(0x01);
+    /// Hidden
+    pub const HIDDEN: DosAttributes = DosAttributes(0x02);
+    /// System
+    pub const SYSTEM: DosAttributes = DosAttributes(0x04);
+    /// Directory
+    pub const DIRECTORY: DosAttributes = DosAttributes(0x10);
+    /// Archive (modified since last backup)
+    pub const ARCHIVE: DosAttributes = DosAttributes(0x20);
+    /// No other attributes set
+    pub const NORMAL: DosAttributes = DosAttributes(0x80);
+
+    /// No attributes.
+    pub fn empty() -> DosAttributes {
+        DosAttributes(0)
+    }
+
+    /// Raw `FILE_ATTRIBUTE_*` bits.
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Check if all attributes in `other` are set.
+    pub fn contains(self, other: DosAttributes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Attributes without those in `other`.
+    pub fn without(self, other: DosAttributes) -> DosAttributes {
+        DosAttributes(self.0 & !other.0)
+    }
+
+    /// Parse hex bitmask like `0x21`.
+    fn parse(mode: &str) -> Result<DosAttributes> {
+        let mode = mode.trim();
+        let hex = mode.trim_start_matches("0x").trim_start_matches("0X");
+        match u32::from_str_radix(hex, 16) {
+            Ok(bits) => Ok(DosAttributes(bits)),
+            Err(_) => {
+                let msg = format!("invalid DOS attributes {:?}", mode);
+                Err(io::Error::new(io::ErrorKind::InvalidData, msg).into())
+            }
+        }
+    }
+} // 2}}}
+
+impl BitOr for DosAttributes {
+    type Output = DosAttributes;
+
+    fn bitor(self, other: DosAttributes) -> DosAttributes {
+        DosAttributes(self.0 | other.0)
+    }
+}
+// 1}}}
+
+// DosInfo {{{1
+impl DosInfo {
+    // {{{2
+    /// DOS attributes.
+    pub fn attributes(&self) -> DosAttributes {
+        self.attributes
+    }
+
+    /// Check if entry is read-only.
+    pub fn readonly(&self) -> bool {
+        self.attributes.contains(DosAttributes::READONLY)
+    }
+
+    /// Check if entry is hidden.
+    pub fn hidden(&self) -> bool {
+        self.attributes.contains(DosAttributes::HIDDEN)
+    }
+
+    /// Check if entry is system one.
+    pub fn system(&self) -> bool {
+        self.attributes.contains(DosAttributes::SYSTEM)
+    }
+
+    /// Check if entry is marked for archiving (modified since last backup).
+    pub fn archive(&self) -> bool {
+        self.attributes.contains(DosAttributes::ARCHIVE)
+    }
+
+    /// Creation time (UTC), `None` unless full time names are used.
+    pub fn created(&self) -> Option<SystemTime> {
+        self.created
+    }
+
+    /// Last access time (UTC).
+    pub fn accessed(&self) -> Option<SystemTime> {
+        self.accessed
+    }
+
+    /// Last write time (UTC).
+    pub fn written(&self) -> Option<SystemTime> {
+        self.written
+    }
+
+    /// Last change time (UTC).
+    pub fn changed(&self) -> Option<SystemTime> {
+        self.changed
+    }
+
+    /// Replace DOS attributes.
+    pub fn with_attributes(mut self, attributes: DosAttributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// Replace creation time.
+    pub fn with_created(mut self, time: SystemTime) -> Self {
+        self.created = Some(time);
+        self
+    }
+
+    /// Replace last access time.
+    pub fn with_accessed(mut self, time: SystemTime) -> Self {
+        self.accessed = Some(time);
+        self
+    }
+
+    /// Replace last write time.
+    pub fn with_written(mut self, time: SystemTime) -> Self {
+        self.written = Some(time);
+        self
+    }
+
+    /// Replace last change time.
+    pub fn with_changed(mut self, time: SystemTime) -> Self {
+        self.changed = Some(time);
+        self
+    }
+
+    /// Parse `system.dos_attr.*` value like `MODE:0x20,SIZE:3,A_TIME:1500000000,...`.
+    ///
+    /// Both short (`A_TIME`) and full (`ACCESS_TIME`) time names are accepted.
+    fn parse(value: &str) -> Result<DosInfo> {
+        let invalid = || {
+            let msg = format!("invalid DOS attributes {:?}", value);
+            Error::from(io::Error::new(io::ErrorKind::InvalidData, msg))
+        };
+        let time = |secs: &str| -> Result<Option<SystemTime>> {
+            let secs = secs.trim().parse::<i64>().map_err(|_| invalid())?;
+            Ok(Some(to_system_time(secs as libc::time_t, 0)))
+        };
+
+        let mut info = DosInfo {
+            attributes: DosAttributes::empty(),
+            created: None,
+            accessed: None,
+            written: None,
+            changed: None,
+        };
+        for field in value.trim().split(',') {
+            let idx = field.find(':').ok_or_else(invalid)?;
+            let (name, value) = (&field[..idx], &field[idx + 1..]);
+            match name {
+                "MODE" => info.attributes = DosAttributes::parse(value)?,
+                "CREATE_TIME" => info.created = time(value)?,
+                "ACCESS_TIME" | "A_TIME" => info.accessed = time(value)?,
+                "WRITE_TIME" | "M_TIME" => info.written = time(value)?,
+                "CHANGE_TIME" | "C_TIME" => info.changed = time(value)?,
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+
+    /// Format as `system.dos_attr.*` value with time names `libsmbclient` expects.
+    fn to_xattr(&self, full_time_names: bool) -> String {
+        let names = if full_time_names {
+            ["ACCESS_TIME", "WRITE_TIME", "CHANGE_TIME"]
+        } else {
+            ["A_TIME", "M_TIME", "C_TIME"]
+        };
+        let mut value = format!("MODE:0x{:x}", self.attributes.0);
+        let times = [
+            ("CREATE_TIME", self.created),
+            (names[0], self.accessed),
+            (names[1], self.written),
+            (names[2], self.changed),
+        ];
+        for &(name, time) in &times {
+            if let Some(time) = time {
+                let secs = to_timeval(time).tv_sec;
+                value.push_str(&format!(",{}:{}", name, secs));
+            }
+        }
+        value
+    }
+} // 2}}}
+  // 1}}}
+
+// Watcher {{{1
+impl<'a, 'b> Iterator for Watcher<'a, 'b> {
+    // {{{2
+    type Item = Result<ChangeEvent>;
+
+    /// Blocks until server reports next change.
+    ///
+    /// Server keeps collecting changes while directory is open,
+    /// so nothing is lost between calls. Ends after first error.
+    fn next(&mut self) -> Option<Result<ChangeEvent>> {
+        while self.pending.is_empty() {
+            if self.failed {
+                return None;
+            }
+            let pending = &mut self.pending;
+            let res = self.dir.notify(self.recursive, self.filter, |events| {
+                pending.extend(events);
+                pending.is_empty()
+            });
+            if let Err(err) = res {
+                self.failed = true;
+                return Some(Err(err));
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+} // 2}}}
+  // 1}}}
+
+// ReadDir {{{1
+impl<'a, 'b> ReadDir<'a, 'b> {
+    // {{{2
+    /// Position of next entry, see
+    /// [`SmbDirectory::tell()`](struct.SmbDirectory.html#method.tell).
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn first_pages(client: &smbc::SmbClient, dir: &str) -> smbc::Result<()> {
+    ///     let mut entries = client.read_dir(dir)?;
+    ///     let first: Vec<_> = entries.by_ref().take(1000).collect();
+    ///     let resume_at = entries.tell()?;
+    ///     // ... process first page, then later continue with the same iterator
+    ///     entries.seek(resume_at)?;
+    ///     let second: Vec<_> = entries.take(1000).collect();
+    /// #   let _ = (first, second);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn tell(&self) -> Result<DirOffset> {
+        self.dir.tell()
+    }
+
+    /// Continue from `offset` returned by
+    /// [`tell()`](struct.ReadDir.html#method.tell) of this iterator.
+    pub fn seek(&mut self, offset: DirOffset) -> Result<()> {
+        self.dir.seek(offset)
+    }
+} // 2}}}
+
+impl<'a, 'b> Iterator for ReadDir<'a, 'b> {
+    // {{{2
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Result<DirEntry>> {
+        let readdir_fn = match self.dir.smbc.get_fn(smbc_getFunctionReaddir) {
+            Ok(readdir_fn) => readdir_fn,
+            Err(err) => return Some(Err(err.into())),
+        };
+        loop {
+            let dirent = readdir_fn(self.dir.smbc.ctx, self.dir.fd);
+            if dirent.is_null() {
+                // end of listing unless auth failed while it was fetched
+                return self.dir.smbc.check(Ok(())).err().map(Err);
+            }
+            let dirent = unsafe { &*dirent };
+            let name = unsafe { cstr(dirent.name.as_ptr()) };
+            if name == "." || name == ".." {
+                continue;
+            }
+            let kind = match EntryType::from_native(dirent.smbc_type) {
+                Some(kind) => kind,
+                None => {
+                    trace!(target: "smbc", "skipping {:?} of type {}", name, dirent.smbc_type);
+                    continue;
+                }
+            };
+
+            // workgroups and servers are addressed directly, not by parent URL
+            let path = match kind {
+                EntryType::Workgroup | EntryType::Server => entry_path("smb://", &name),
+                _ => entry_path(&self.path, &name),
+            };
+            let comment = if dirent.comment.is_null() {
+                String::new()
+            } else {
+                unsafe { cstr(dirent.comment) }.into_owned()
+            };
+            return Some(Ok(DirEntry {
+                path,
+                name: name.into_owned(),
+                kind,
+                comment,
+            }));
+        }
+    }
+} // 2}}}
+  // 1}}}
+
+// ReadDirPlus {{{1
+impl<'a, 'b> Iterator for ReadDirPlus<'a, 'b> {
+    // {{{2
+    type Item = Result<(DirEntry, Metadata)>;
+
+    fn next(&mut self) -> Option<Result<(DirEntry, Metadata)>> {
+        if let Some(readdirplus_fn) = self.readdirplus_fn {
+            let smbc = self.inner.dir.smbc;
+            loop {
+                let info = readdirplus_fn(smbc.ctx, self.inner.dir.fd);
+                if info.is_null() {
+                    if self.started {
+                        return smbc.check(Ok(())).err().map(Err);
+                    }
+                    // only file listings have attributes, fall back for shares, servers etc.
+                    trace!(target: "smbc", "readdirplus gave nothing, falling back to readdir");
+                    self.readdirplus_fn = None;
+                    break;
+                }
+                self.started = true;
+                let info = unsafe { &*info };
+                let name = unsafe { cstr(info.name) };
+                if name == "." || name == ".." {
+                    continue;
+                }
+                let metadata = Metadata::from_file_info(info);
+                let kind = if metadata.is_dir() {
+                    EntryType::Dir
+                } else {
+                    EntryType::File
+                };
+                let entry = DirEntry {
+                    path: entry_path(&self.inner.path, &name),
+                    name: name.into_owned(),
+                    kind,
+                    comment: String::new(),
+                };
+                return Some(Ok((entry, metadata)));
+            }
+        }
+
+        let entry = match self.inner.next()? {
+            Ok(entry) => entry,
+            Err(err) => return Some(Err(err)),
+        };
+        match self.inner.dir.smbc.metadata(&entry.path) {
+            Ok(metadata) => Some(Ok((entry, metadata))),
+            Err(err) => Some(Err(err)),
+        }
+    }
+} // 2}}}
+  // 1}}}
+
+// Walk {{{1
+impl<'a, 'b> Walk<'a, 'b> {
+    // {{{2
+    /// Don't descend deeper than `depth` (`1` yields only entries of `root`).
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Descend into DFS links and symbolic links (disabled by default).
+    ///
+    /// There is no loop detection, so limit depth if links may point up the tree.
+    pub fn follow_links(mut self, follow_links: bool) -> Self {
+        self.follow_links = follow_links;
+        self
+    }
+
+    /// Yield entries of each directory sorted by name (disabled by default).
+    ///
+    /// Directory is listed completely before its first entry is yielded.
+    pub fn sort_by_name(mut self, sort_by_name: bool) -> Self {
+        self.sort_by_name = sort_by_name;
+        self
+    }
+
+    /// Include hidden (administrative) shares when walking server (disabled by default).
+    pub fn hidden_shares(mut self, hidden_shares: bool) -> Self {
+        self.hidden_shares = hidden_shares;
+        self
+    }
+
+    /// Don't descend into directory yielded last.
+    ///
+    /// Does nothing if last entry isn't directory walk would descend into.
+    pub fn skip_current_dir(&mut self) {
+        self.pending = None;
+    }
+
+    fn push(&mut self, path: &str) -> Result<()> {
+        trace!(target: "smbc", "walking into {}", path);
+        let entries = at_path(path, self.smbc.read_dir_plus(path))?;
+        let level = if self.sort_by_name {
+            let mut entries: Vec<_> = entries.collect();
+            entries.sort_by(|a, b| walk_entry_name(a).cmp(&walk_entry_name(b)));
+            WalkLevel::Sorted(entries.into_iter())
+        } else {
+            WalkLevel::Dir(entries)
+        };
+        self.stack.push(level);
+        Ok(())
+    }
+
+    fn should_descend(&self, entry: &DirEntry, metadata: &Metadata) -> bool {
+        match entry.kind {
+            EntryType::Share(ShareKind::Disk) => true,
+            EntryType::Dir => self.follow_links || metadata.file_type() != FileType::Symlink,
+            EntryType::Link => self.follow_links,
+            _ => false,
+        }
+    }
+} // 2}}}
+
+impl<'a, 'b> Iterator for Walk<'a, 'b> {
+    // {{{2
+    type Item = Result<(usize, DirEntry, Metadata)>;
+
+    fn next(&mut self) -> Option<Result<(usize, DirEntry, Metadata)>> {
+        // directories are opened only when walk proceeds into them
+        if let Some(path) = self.pending.take() {
+            if self.stack.len() < self.max_depth {
+                if let Err(err) = self.push(&path) {
+                    return Some(Err(err));
+                }
+            }
+        }
+        loop {
+            let depth = self.stack.len();
+            let next = match self.stack.last_mut() {
+                Some(WalkLevel::Dir(entries)) => entries.next(),
+                Some(WalkLevel::Sorted(entries)) => entries.next(),
+                None => return None,
+            };
+            let (entry, metadata) = match next {
+                Some(Ok(item)) => item,
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            if let EntryType::Share(_) = entry.kind {
+                if !self.hidden_shares && entry.name.ends_with('$') {
+                    continue;
+                }
+            }
+            if depth < self.max_depth && self.should_descend(&entry, &metadata) {
+                self.pending = Some(entry.path.clone());
+            }
+            return Some(Ok((depth, entry, metadata)));
         }
     }
 } // 2}}}
   // 1}}}
 
-// OpenOptions {{{1
-/// Describes options for opening file:
-///
-/// * `read` if readable;
-/// * `write` if writable;
-/// * `flags` is *bitwise OR* of `O_CREAT`, `O_EXCL` and `O_TRUNC`;
-/// * `mode` for *POSIX* file mode.
-#[derive(Clone, Copy, Debug)]
-pub struct OpenOptions {
-    flags: c_int,
-    read: bool,
-    write: bool,
-    mode: mode_t,
-}
+// DirSize {{{1
+impl DirSize {
+    // {{{2
+    /// Total size of files in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Number of files (everything except directories).
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// Number of directories (not counting root).
+    pub fn dirs(&self) -> u64 {
+        self.dirs
+    }
+} // 2}}}
+  // 1}}}
+
+// TreeNode {{{1
+impl TreeNode {
+    // {{{2
+    /// Name of entry (for root, last component of its URL).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Check if entry is directory.
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// Entries of directory sorted by name, empty for files
+    /// and directories at depth limit.
+    pub fn children(&self) -> &[TreeNode] {
+        &self.children
+    }
+
+    /// Metadata of entry.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+} // 2}}}
+  // 1}}}
+
+// TreeTransfer {{{1
+impl TreeTransfer {
+    // {{{2
+    /// Total size of files copied in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
 
-impl OpenOptions {
+    /// Number of files copied.
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// Number of directories copied (not counting root).
+    pub fn dirs(&self) -> u64 {
+        self.dirs
+    }
+
+    /// Failures of files and directories which weren't copied.
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    /// Check if whole tree was copied.
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+} // 2}}}
+  // 1}}}
+
+// Progress {{{1
+impl Progress {
     // {{{2
-    /// Allows reading file (set by default).
-    pub fn read(mut self, read: bool) -> Self {
-        self.read = read;
-        self
+    /// Progress of transfer of single file at `path`
+    pub(crate) fn single(path: &str, copied: u64, size: u64) -> Progress {
+        Progress {
+            path: path.to_owned(),
+            file_bytes: copied,
+            file_size: size,
+            bytes: copied,
+            files: 0,
+            total_bytes: Some(size),
+            total_files: Some(1),
+        }
     }
 
-    /// Allows writing to file.
-    pub fn write(mut self, write: bool) -> Self {
-        self.write = write;
-        self
+    /// Progress of current file as part of operation which already did `done`
+    pub(crate) fn within(
+        &self,
+        done: &TreeTransfer,
+        total_bytes: Option<u64>,
+        total_files: Option<u64>,
+    ) -> Progress {
+        Progress {
+            bytes: done.bytes + self.file_bytes,
+            files: done.files,
+            total_bytes,
+            total_files,
+            ..self.clone()
+        }
     }
 
-    /// Allows appending to file.
-    pub fn append(mut self, append: bool) -> Self {
-        self.flag(libc::O_APPEND, append);
-        self
+    /// SMB path of file being transferred.
+    pub fn path(&self) -> &str {
+        &self.path
     }
 
-    /// Allows creating file if it doesn't exists.
+    /// Bytes of current file copied so far.
+    pub fn file_bytes(&self) -> u64 {
+        self.file_bytes
+    }
+
+    /// Size of current file in bytes.
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Bytes copied so far by whole operation.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Number of files completed before current one.
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// Total bytes to copy if known in advance.
     ///
-    /// Opening file will fail in case file exists if
-    /// [`exclusive`](struct.OpenOptions.html#method.exclusive)
-    /// also set.
-    pub fn create(mut self, create: bool) -> Self {
-        self.flag(libc::O_CREAT, create);
-        self
+    /// Tree transfers don't list trees before copying, so it's `None` for them.
+    pub fn total_bytes(&self) -> Option<u64> {
+        self.total_bytes
     }
 
-    /// File will be truncated (size set to `0`)
-    /// if it's already exists.
-    pub fn truncate(mut self, truncate: bool) -> Self {
-        self.flag(libc::O_TRUNC, truncate);
-        self
+    /// Total number of files to copy if known in advance.
+    pub fn total_files(&self) -> Option<u64> {
+        self.total_files
     }
+} // 2}}}
+  // 1}}}
 
-    /// `open_*` will fail if file already exists
-    /// (when used with `create` also set).
+// DirEntry {{{1
+impl DirEntry {
+    // {{{2
+    /// Full SMB URL of entry (name is percent-encoded).
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Name of entry.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Type of entry as reported by directory listing,
+    /// known without extra request to server.
+    pub fn entry_type(&self) -> EntryType {
+        self.kind
+    }
+
+    /// Metadata of entry, requested from server with `client`.
     ///
-    /// See [`create`](struct.OpenOptions.html#method.create)
-    pub fn exclusive(mut self, exclusive: bool) -> Self {
-        self.flag(libc::O_EXCL, exclusive);
-        self
+    /// Listing gives type of entry only, so check
+    /// [`entry_type()`](#method.entry_type) first to skip ones which
+    /// aren't interesting. Use
+    /// [`read_dir_plus(..)`](struct.SmbClient.html#method.read_dir_plus)
+    /// to get metadata of all entries.
+    ///
+    /// ```rust
+    /// # fn main() {}
+    /// #
+    /// fn subdirs_mtime(client: &smbc::SmbClient, dir: &str) -> smbc::Result<()> {
+    ///     for entry in client.read_dir(dir)? {
+    ///         let entry = entry?;
+    ///         if entry.entry_type() == smbc::EntryType::Dir {
+    ///             println!("{} {:?}", entry.name(), entry.metadata(client)?.modified());
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn metadata(&self, client: &SmbClient) -> Result<Metadata> {
+        client.metadata(&self.path)
     }
 
-    /// Set POSIX file mode
-    pub fn mode(mut self, mode: mode_t) -> Self {
-        self.mode = mode;
-        self
+    /// Comment (description) of share, server or workgroup,
+    /// empty for files and directories.
+    pub fn comment(&self) -> &str {
+        &self.comment
     }
+} // 2}}}
 
-    fn flag(&mut self, flag: c_int, on: bool) {
-        if on {
-            self.flags |= flag;
-        } else {
-            self.flags &= !flag;
+impl EntryType {
+    // {{{2
+    fn from_native(smbc_type: c_uint) -> Option<EntryType> {
+        match smbc_type {
+            SMBC_WORKGROUP => Some(EntryType::Workgroup),
+            SMBC_SERVER => Some(EntryType::Server),
+            SMBC_FILE_SHARE => Some(EntryType::Share(ShareKind::Disk)),
+            SMBC_PRINTER_SHARE => Some(EntryType::Share(ShareKind::Printer)),
+            SMBC_COMMS_SHARE => Some(EntryType::Share(ShareKind::Comms)),
+            SMBC_IPC_SHARE => Some(EntryType::Share(ShareKind::Ipc)),
+            SMBC_DIR => Some(EntryType::Dir),
+            SMBC_FILE => Some(EntryType::File),
+            SMBC_LINK => Some(EntryType::Link),
+            _ => None,
         }
     }
+} // 2}}}
+  // 1}}}
 
-    /// Naive impl, rewrite to check for incompatible flags
-    fn to_flags(&self) -> Result<c_int> {
-        let base_mode = match (self.read, self.write) {
-            // defaults to read only
-            (false, false) | (true, false) => libc::O_RDONLY,
-            (false, true) => libc::O_WRONLY,
-            (true, true) => libc::O_RDWR,
-        };
-        Ok(base_mode | self.flags)
+// NotifyFilter {{{1
+impl NotifyFilter {
+    // {{{2
+    /// File created, removed or renamed
+    pub const FILE_NAME: NotifyFilter = NotifyFilter(0x001);
+    /// Directory created, removed or renamed
+    pub const DIR_NAME: NotifyFilter = NotifyFilter(0x002);
+    /// Attributes changed
+    pub const ATTRIBUTES: NotifyFilter = NotifyFilter(0x004);
+    /// Size changed
+    pub const SIZE: NotifyFilter = NotifyFilter(0x008);
+    /// Last write time changed
+    pub const LAST_WRITE: NotifyFilter = NotifyFilter(0x010);
+    /// Last access time changed
+    pub const LAST_ACCESS: NotifyFilter = NotifyFilter(0x020);
+    /// Creation time changed
+    pub const CREATION: NotifyFilter = NotifyFilter(0x040);
+    /// Extended attributes changed
+    pub const EA: NotifyFilter = NotifyFilter(0x080);
+    /// Security descriptor changed
+    pub const SECURITY: NotifyFilter = NotifyFilter(0x100);
+    /// Named stream created, removed or renamed
+    pub const STREAM_NAME: NotifyFilter = NotifyFilter(0x200);
+    /// Named stream size changed
+    pub const STREAM_SIZE: NotifyFilter = NotifyFilter(0x400);
+    /// Named stream written
+    pub const STREAM_WRITE: NotifyFilter = NotifyFilter(0x800);
+
+    /// Empty filter.
+    pub fn empty() -> NotifyFilter {
+        NotifyFilter(0)
     }
-} // }}}
-  // 1}}}
 
-impl Default for OpenOptions {
-    /// Default [`OpenOptions`](struct.OpenOptions.html) is
-    /// read-only with POSIX perms `0644`
-    /// (`rw` for owner, `r` for group and others).
-    fn default() -> OpenOptions {
-        OpenOptions {
-            flags: 0,
-            read: true,
-            write: false,
-            mode: 0o644,
-        }
+    /// All changes.
+    pub fn all() -> NotifyFilter {
+        NotifyFilter(0xfff)
+    }
+
+    /// Raw `SMBC_NOTIFY_CHANGE_*` bits.
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Check if all changes in `other` are included.
+    pub fn contains(self, other: NotifyFilter) -> bool {
+        self.0 & other.0 == other.0
+    }
+} // 2}}}
+
+impl BitOr for NotifyFilter {
+    type Output = NotifyFilter;
+
+    fn bitor(self, other: NotifyFilter) -> NotifyFilter {
+        NotifyFilter(self.0 | other.0)
     }
 }
+// 1}}}
 
-// SmbFile {{{1
-impl<'a, 'b> SmbFile<'a, 'b> {
+// ChangeEvent {{{1
+impl ChangeAction {
     // {{{2
-} // }}}
+    fn from_native(action: u32) -> Option<ChangeAction> {
+        match action {
+            SMBC_NOTIFY_ACTION_ADDED => Some(ChangeAction::Added),
+            SMBC_NOTIFY_ACTION_REMOVED => Some(ChangeAction::Removed),
+            SMBC_NOTIFY_ACTION_MODIFIED => Some(ChangeAction::Modified),
+            SMBC_NOTIFY_ACTION_OLD_NAME => Some(ChangeAction::RenamedFrom),
+            SMBC_NOTIFY_ACTION_NEW_NAME => Some(ChangeAction::RenamedTo),
+            SMBC_NOTIFY_ACTION_ADDED_STREAM => Some(ChangeAction::AddedStream),
+            SMBC_NOTIFY_ACTION_REMOVED_STREAM => Some(ChangeAction::RemovedStream),
+            SMBC_NOTIFY_ACTION_MODIFIED_STREAM => Some(ChangeAction::ModifiedStream),
+            _ => None,
+        }
+    }
+} // 2}}}
 
-impl<'a, 'b> Read for SmbFile<'a, 'b> {
+impl ChangeEvent {
+    // {{{2
+    /// What happened.
+    pub fn action(&self) -> ChangeAction {
+        self.action
+    }
+
+    /// Name of changed entry relative to watched directory.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+} // 2}}}
+  // 1}}}
+
+// ConcatRead {{{1
+impl<'a, 'b> Read for ConcatRead<'a, 'b> {
     // {{{2
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        trace!(target: "smbc", "reading file to buf [{:?};{}]", buf.as_ptr(), buf.len());
-        let read_fn = self.smbc.get_fn(smbc_getFunctionRead)?;
-        let bytes_read = to_result_with_le(read_fn(
-            self.smbc.ctx,
-            self.fd,
-            buf.as_mut_ptr() as *mut c_void,
-            buf.len() as _,
-        ))?;
-        Ok(bytes_read as usize)
+        let smbc = self.smbc;
+        read_concat(
+            buf,
+            &self.paths,
+            &mut self.next,
+            &mut self.current,
+            |path| smbc.open(path),
+        )
     }
 } // }}}
 
-impl<'a, 'b> Write for SmbFile<'a, 'b> {
-    // {{{2
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        trace!(target: "smbc", "writing buf [{:?};{}] to file", buf.as_ptr(), buf.len());
-        let write_fn = self.smbc.get_fn(smbc_getFunctionWrite)?;
-        let bytes_wrote = to_result_with_le(write_fn(
-            self.smbc.ctx,
-            self.fd,
-            buf.as_ptr() as *const c_void,
-            buf.len() as _,
-        ))?;
-        Ok(bytes_wrote as usize)
+/// Read from `current` file, opening next of `paths` with `open`
+/// when it's exhausted, `Ok(0)` after the last one.
+fn read_concat<R, F>(
+    buf: &mut [u8],
+    paths: &[String],
+    next: &mut usize,
+    current: &mut Option<R>,
+    mut open: F,
+) -> io::Result<usize>
+where
+    R: Read,
+    F: FnMut(&str) -> Result<R>,
+{
+    if buf.is_empty() {
+        return Ok(0);
+    }
+    loop {
+        if current.is_none() {
+            if *next == paths.len() {
+                return Ok(0);
+            }
+            let path = &paths[*next];
+            trace!(target: "smbc", "concat_read: switching to {}", path);
+            *next += 1;
+            *current = Some(open(path)?);
+        }
+        if let Some(ref mut file) = *current {
+            let bytes_read = file.read(buf)?;
+            if bytes_read > 0 {
+                return Ok(bytes_read);
+            }
+        }
+        *current = None;
     }
+}
+// 1}}}
 
-    /// Do nothing for SmbFile
-    fn flush(&mut self) -> io::Result<()> {
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_ranges_cover_file() {
+        assert_eq!(byte_ranges(0, 1024, 4), vec![(0, 0)]);
+        assert_eq!(byte_ranges(10, 1024, 4), vec![(0, 10)]);
+        assert_eq!(byte_ranges(4096, 1024, 2), vec![(0, 2048), (2048, 2048)]);
+        // rounded up range length leaves fewer ranges than streams
+        assert_eq!(byte_ranges(5, 1, 4), vec![(0, 2), (2, 2), (4, 1)]);
+        for &(total, chunk, streams) in &[(7, 2, 3), (1000, 7, 9), (3, 1, 8)] {
+            let ranges = byte_ranges(total, chunk, streams);
+            assert!(ranges.len() as u64 <= streams);
+            let mut end = 0;
+            for &(start, len) in &ranges {
+                assert_eq!(start, end);
+                assert!(len > 0);
+                end += len;
+            }
+            assert_eq!(end, total);
+        }
     }
-} // }}}
 
-impl<'a, 'b> Seek for SmbFile<'a, 'b> {
-    // {{{2
-    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        trace!(target: "smbc", "seeking file {:?}", pos);
-        let lseek_fn = self.smbc.get_fn(smbc_getFunctionLseek)?;
-        let (whence, off) = match pos {
-            SeekFrom::Start(p) => (libc::SEEK_SET, p as off_t),
-            SeekFrom::End(p) => (libc::SEEK_END, p as off_t),
-            SeekFrom::Current(p) => (libc::SEEK_CUR, p as off_t),
+    #[test]
+    fn dos_attributes_parse() {
+        let attrs = DosAttributes::parse(" 0x21 ").unwrap();
+        assert!(attrs.contains(DosAttributes::READONLY | DosAttributes::ARCHIVE));
+        assert!(!attrs.contains(DosAttributes::HIDDEN));
+        assert_eq!(
+            DosAttributes::parse("0X10").unwrap(),
+            DosAttributes::DIRECTORY
+        );
+        assert_eq!(DosAttributes::parse("80").unwrap(), DosAttributes::NORMAL);
+        assert!(DosAttributes::parse("0xzz").is_err());
+        assert!(DosAttributes::parse("").is_err());
+    }
+
+    #[test]
+    fn dos_info_parse() {
+        let info =
+            DosInfo::parse("MODE:0x22,SIZE:3,A_TIME:100,M_TIME:200,C_TIME:300,INODE:5").unwrap();
+        assert!(info.hidden() && info.archive() && !info.readonly());
+        assert_eq!(info.created(), None);
+        assert_eq!(info.accessed(), Some(UNIX_EPOCH + Duration::from_secs(100)));
+        assert_eq!(info.written(), Some(UNIX_EPOCH + Duration::from_secs(200)));
+        assert_eq!(info.changed(), Some(UNIX_EPOCH + Duration::from_secs(300)));
+
+        let info = DosInfo::parse("MODE:0x1,CREATE_TIME:1,ACCESS_TIME:2,WRITE_TIME:3").unwrap();
+        assert!(info.readonly());
+        assert_eq!(info.created(), Some(UNIX_EPOCH + Duration::from_secs(1)));
+        assert_eq!(info.written(), Some(UNIX_EPOCH + Duration::from_secs(3)));
+
+        assert!(DosInfo::parse("MODE").is_err());
+        assert!(DosInfo::parse("MODE:0x20,A_TIME:soon").is_err());
+    }
+
+    #[test]
+    fn dos_info_to_xattr() {
+        let info = DosInfo::parse("MODE:0x20,A_TIME:100,M_TIME:200").unwrap();
+        assert_eq!(info.to_xattr(false), "MODE:0x20,A_TIME:100,M_TIME:200");
+        assert_eq!(
+            info.to_xattr(true),
+            "MODE:0x20,ACCESS_TIME:100,WRITE_TIME:200"
+        );
+        let info = info
+            .with_attributes(DosAttributes::HIDDEN)
+            .with_created(UNIX_EPOCH + Duration::from_secs(50));
+        assert_eq!(
+            info.to_xattr(false),
+            "MODE:0x2,CREATE_TIME:50,A_TIME:100,M_TIME:200"
+        );
+        assert_eq!(DosInfo::parse(&info.to_xattr(true)).unwrap(), info);
+    }
+
+    /// Reads `read_concat` over `files` with buffer of `buf_size` bytes
+    fn concat(files: &[&str], buf_size: usize) -> (Vec<String>, Vec<String>) {
+        let paths: Vec<String> = files.iter().map(|f| f.to_string()).collect();
+        let (mut next, mut current) = (0, None);
+        let mut opened = Vec::new();
+        let mut reads = Vec::new();
+        loop {
+            let mut buf = vec![0; buf_size];
+            let n = read_concat(&mut buf, &paths, &mut next, &mut current, |path| {
+                opened.push(path.to_owned());
+                Ok(io::Cursor::new(path.as_bytes().to_vec()))
+            })
+            .unwrap();
+            if n == 0 {
+                return (reads, opened);
+            }
+            reads.push(String::from_utf8(buf[..n].to_vec()).unwrap());
+        }
+    }
+
+    #[test]
+    fn concat_read_boundaries() {
+        // single read never spans two files
+        let (reads, opened) = concat(&["ab", "cde"], 4);
+        assert_eq!(reads, vec!["ab", "cde"]);
+        assert_eq!(opened, vec!["ab", "cde"]);
+        let (reads, _) = concat(&["abc", "d"], 2);
+        assert_eq!(reads, vec!["ab", "c", "d"]);
+        // empty files are skipped instead of ending stream
+        let (reads, opened) = concat(&["", "ab", "", ""], 8);
+        assert_eq!(reads, vec!["ab"]);
+        assert_eq!(opened.len(), 4);
+        assert!(concat(&[], 8).0.is_empty());
+        assert!(concat(&["ab"], 0).1.is_empty());
+    }
+
+    #[test]
+    fn concat_read_open_error() {
+        let paths = vec!["a".to_owned(), "missing".to_owned(), "b".to_owned()];
+        let (mut next, mut current) = (0, None);
+        let mut buf = [0; 8];
+        let mut open = |path: &str| {
+            if path == "missing" {
+                Err(Error::NotFound)
+            } else {
+                Ok(io::Cursor::new(path.as_bytes().to_vec()))
+            }
         };
-        let res = lseek_fn(self.smbc.ctx, self.fd, off, whence);
-        let res = to_result_with_errno(res, libc::EINVAL)?;
-        Ok(res as u64)
+        assert_eq!(
+            read_concat(&mut buf, &paths, &mut next, &mut current, &mut open).unwrap(),
+            1
+        );
+        let err = read_concat(&mut buf, &paths, &mut next, &mut current, &mut open).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        // failed file is skipped by next read
+        assert_eq!(
+            read_concat(&mut buf, &paths, &mut next, &mut current, &mut open).unwrap(),
+            1
+        );
+        assert_eq!(&buf[..1], b"b");
     }
-} // }}}
 
-impl<'a, 'b> Drop for SmbFile<'a, 'b> {
-    // {{{2
-    fn drop(&mut self) {
-        trace!(target: "smbc", "closing file");
-        if let Ok(close_fn) = self.smbc.get_fn(smbc_getFunctionClose) {
-            close_fn(self.smbc.ctx, self.fd);
+    fn rejected(options: OpenOptions) -> bool {
+        matches!(options.to_flags(), Err(Error::InvalidOpenOptions(_)))
+    }
+
+    #[test]
+    fn open_options_to_flags() {
+        let options = OpenOptions::default();
+        assert_eq!(options.to_flags().unwrap(), libc::O_RDONLY);
+        let options = OpenOptions::default()
+            .write(true)
+            .create(true)
+            .truncate(true);
+        assert_eq!(
+            options.to_flags().unwrap(),
+            libc::O_RDWR | libc::O_CREAT | libc::O_TRUNC
+        );
+        let options = OpenOptions::default().read(false).append(true);
+        assert_eq!(options.to_flags().unwrap(), libc::O_WRONLY | libc::O_APPEND);
+        // truncation is dropped, new file is empty anyway
+        let options = OpenOptions::default()
+            .write(true)
+            .truncate(true)
+            .create_new(true);
+        assert_eq!(
+            options.to_flags().unwrap(),
+            libc::O_RDWR | libc::O_CREAT | libc::O_EXCL
+        );
+    }
+
+    #[test]
+    fn open_options_rejected() {
+        assert!(rejected(OpenOptions::default().truncate(true)));
+        assert!(rejected(OpenOptions::default().create(true)));
+        assert!(rejected(OpenOptions::default().create_new(true)));
+        assert!(rejected(OpenOptions::default().write(true).exclusive(true)));
+        assert!(rejected(
+            OpenOptions::default()
+                .write(true)
+                .append(true)
+                .truncate(true)
+        ));
+        // appending without write still can't truncate
+        assert!(rejected(OpenOptions::default().append(true).truncate(true)));
+        assert!(!rejected(
+            OpenOptions::default()
+                .write(true)
+                .create(true)
+                .exclusive(true)
+        ));
+    }
+
+    #[test]
+    fn acl_to_restore_keeps_custom_acl() {
+        let custom = "REVISION:1,OWNER:S-1-5-21-1-2-3-1001,GROUP:S-1-5-21-1-2-3-513,\
+                      ACL:S-1-5-21-1-2-3-1001:0/0/0x001f01ff,ACL:S-1-1-0:1/0/0x00000002";
+        let sd: SecurityDescriptor = custom.parse().unwrap();
+        let path = "smb://host/share/file";
+        let restored = acl_to_restore(path, Ok(sd.clone())).unwrap();
+        assert_eq!(restored.aces(), sd.aces());
+        assert_eq!(restored.revision(), 1);
+        assert_eq!((restored.owner(), restored.group()), (None, None));
+        assert!(!restored.to_string().contains("OWNER"));
+
+        assert!(acl_to_restore(path, Err(Error::NotFound)).is_none());
+        assert!(acl_to_restore(path, Err(Error::NotSupported)).is_none());
+        assert!(acl_to_restore(path, Err(Error::PermissionDenied)).is_none());
+    }
+
+    #[test]
+    fn notify_wrapper_keeps_panic() {
+        fn call<F: FnMut(Vec<ChangeEvent>) -> bool>(state: &mut NotifyState<F>) -> c_int {
+            let data = state as *mut NotifyState<F> as *mut c_void;
+            SmbDirectory::notify_wrapper::<F>(ptr::null(), 0, data)
         }
+
+        let mut state = (|events: Vec<ChangeEvent>| events.is_empty(), None);
+        assert_eq!(call(&mut state), 0);
+        assert!(state.1.is_none());
+
+        let mut state = (
+            |_: Vec<ChangeEvent>| -> bool { panic!("callback failed") },
+            None,
+        );
+        assert_eq!(call(&mut state), 1);
+        let payload = state.1.expect("panic payload is kept");
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"callback failed"));
     }
-} // }}}
-  // 1}}}
+
+    #[test]
+    fn local_entry_path_stays_in_dir() {
+        let dir = Path::new("/tmp/download");
+        assert_eq!(
+            local_entry_path(dir, "a b.txt").unwrap(),
+            Path::new("/tmp/download/a b.txt")
+        );
+        assert_eq!(
+            local_entry_path(dir, "..hidden").unwrap(),
+            Path::new("/tmp/download/..hidden")
+        );
+        for name in &[
+            "",
+            ".",
+            "..",
+            "../x",
+            "a/b",
+            "a/",
+            "/etc/passwd",
+            "a/../../x",
+        ] {
+            assert!(local_entry_path(dir, name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn browse_url_strips_scheme() {
+        assert_eq!(browse_url("host"), "smb://host/");
+        assert_eq!(browse_url("smb://host/"), "smb://host/");
+        assert_eq!(browse_url("SMB://host"), "smb://host/");
+        assert_eq!(browse_url("Smb://my group"), "smb://my%20group/");
+    }
+
+    #[test]
+    fn entry_path_joins_name() {
+        assert_eq!(
+            entry_path("smb://host/share/dir", "a b.txt"),
+            "smb://host/share/dir/a%20b.txt"
+        );
+        assert_eq!(
+            entry_path("smb://host/share/dir/", "a b.txt"),
+            "smb://host/share/dir/a%20b.txt"
+        );
+        assert_eq!(entry_path("smb://", "WORKGROUP"), "smb://WORKGROUP");
+        assert_eq!(entry_path("smb://host", "100%"), "smb://host/100%25");
+    }
+
+    /// Listing of fake tree: `dirN` has `N` files and subdirectories
+    /// `dirN0`..`dirN3` while their names are shorter than 5 characters
+    /// `dir` also has `link`, symlink to itself.
+    fn fake_listing(dir: &str) -> Result<Vec<(DirEntry, Metadata)>> {
+        let fake_entry = |name: String, kind, mode| {
+            let entry = DirEntry {
+                path: entry_path(dir, &name),
+                name,
+                kind,
+                comment: String::new(),
+            };
+            (entry, test_metadata(mode | 0o755, 0))
+        };
+        let name = dir.rsplit('/').next().unwrap();
+        let name = if name == "link" { "dir" } else { name };
+        let mut entries = Vec::new();
+        for idx in 0..name.len() {
+            let name = format!("file{}", idx);
+            entries.push(fake_entry(name, EntryType::File, libc::S_IFREG));
+        }
+        if name.len() < 5 {
+            for idx in 0..4 {
+                let name = format!("{}{}", name, idx);
+                entries.push(fake_entry(name, EntryType::Dir, libc::S_IFDIR));
+            }
+        }
+        if name == "dir" {
+            let link = "link".to_owned();
+            entries.push(fake_entry(link, EntryType::Dir, libc::S_IFLNK));
+        }
+        Ok(entries)
+    }
+
+    #[test]
+    fn walk_with_workers_visits_once() {
+        let mut seen = HashMap::new();
+        let res = walk_with_workers(
+            vec![(); 4],
+            "smb://host/share/dir".to_owned(),
+            |_: &(), dir: &str| {
+                thread::sleep(Duration::from_millis(1));
+                fake_listing(dir)
+            },
+            |entry| *seen.entry(entry.path).or_insert(0) += 1,
+        );
+        assert!(res.is_ok());
+        // root has 3 files, link and 4 subdirectories with 4 files each,
+        // which have 4 leaf subdirectories with 5 files each
+        let expected = (3 + 1 + 4) + 4 * (4 + 4) + 16 * 5;
+        assert_eq!(seen.len(), expected);
+        assert!(seen.values().all(|&count| count == 1));
+        assert_eq!(seen["smb://host/share/dir/dir2/dir23/file4"], 1);
+        // link is visited, but not entered
+        assert_eq!(seen["smb://host/share/dir/link"], 1);
+        assert!(!seen.contains_key("smb://host/share/dir/link/file0"));
+    }
+
+    #[test]
+    fn walk_with_workers_resumes_panic() {
+        let res = panic::catch_unwind(|| {
+            walk_with_workers(
+                vec![(); 2],
+                "smb://host/share/dir".to_owned(),
+                |_: &(), dir: &str| {
+                    if dir.ends_with("dir1") {
+                        panic!("listing failed");
+                    }
+                    fake_listing(dir)
+                },
+                |_| {},
+            )
+        });
+        let payload = res.expect_err("panic of listing is resumed");
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"listing failed"));
+    }
+
+    #[test]
+    fn walk_with_workers_stops_on_error() {
+        let res = walk_with_workers(
+            vec![(); 3],
+            "smb://host/share/dir".to_owned(),
+            |_: &(), dir: &str| {
+                if dir.ends_with("dir2") {
+                    Err(Error::PermissionDenied)
+                } else {
+                    fake_listing(dir)
+                }
+            },
+            |_| {},
+        );
+        match res {
+            Err(Error::Path(path, err)) => {
+                assert_eq!(path, "smb://host/share/dir/dir2");
+                assert!(matches!(*err, Error::PermissionDenied));
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    fn timespec(secs: i64) -> libc::timespec {
+        let mut ts: libc::timespec = unsafe { mem::zeroed() };
+        ts.tv_sec = secs as libc::time_t;
+        ts
+    }
+
+    #[test]
+    fn listing_metadata_populated() {
+        // `readdirplus` entry
+        let info = libsmb_file_info {
+            size: 42,
+            attrs: 0x21, // read-only, archive
+            uid: 0,
+            gid: 0,
+            btime_ts: timespec(1),
+            mtime_ts: timespec(200),
+            atime_ts: timespec(100),
+            ctime_ts: timespec(300),
+            name: ptr::null_mut(),
+            short_name: ptr::null_mut(),
+        };
+        let metadata = Metadata::from_file_info(&info);
+        assert!(metadata.is_file());
+        assert_eq!(metadata.len(), 42);
+        assert_eq!(metadata.modified(), UNIX_EPOCH + Duration::from_secs(200));
+        assert_eq!(metadata.accessed(), UNIX_EPOCH + Duration::from_secs(100));
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o544);
+
+        // `stat` fallback
+        let mut st: libc::stat = unsafe { mem::zeroed() };
+        st.st_size = 42;
+        st.st_mode = libc::S_IFDIR | 0o755;
+        st.st_mtime = 200;
+        let metadata = Metadata::from_stat(st);
+        assert!(metadata.is_dir());
+        assert_eq!(metadata.len(), 42);
+        assert_eq!(metadata.modified(), UNIX_EPOCH + Duration::from_secs(200));
+    }
+
+    fn test_metadata(mode: mode_t, size: u64) -> Metadata {
+        Metadata {
+            size,
+            mode,
+            accessed: UNIX_EPOCH,
+            modified: UNIX_EPOCH,
+            changed: UNIX_EPOCH,
+        }
+    }
+
+    fn tree_node(name: &str, is_dir: bool) -> TreeNode {
+        let mode = if is_dir { libc::S_IFDIR } else { libc::S_IFREG };
+        TreeNode {
+            name: name.to_owned(),
+            is_dir,
+            children: Vec::new(),
+            metadata: test_metadata(mode | 0o644, name.len() as u64),
+        }
+    }
+
+    fn tree_shape(node: &TreeNode) -> String {
+        let children: Vec<_> = node.children().iter().map(tree_shape).collect();
+        if node.is_dir() {
+            format!("{}({})", node.name(), children.join(" "))
+        } else {
+            node.name().to_owned()
+        }
+    }
+
+    #[test]
+    fn build_tree_shape() {
+        // root/{a/{b/{c}, d}, e, f/}
+        let nodes = vec![
+            (1, tree_node("a", true)),
+            (2, tree_node("b", true)),
+            (3, tree_node("c", false)),
+            (2, tree_node("d", false)),
+            (1, tree_node("e", false)),
+            (1, tree_node("f", true)),
+        ];
+        let tree = build_tree(
+            tree_node("root", true),
+            nodes.clone().into_iter().map(Ok),
+            6,
+        )
+        .unwrap();
+        assert_eq!(tree_shape(&tree), "root(a(b(c) d) e f())");
+        assert_eq!(tree.children()[0].children()[1].metadata().len(), 1);
+
+        let tree = build_tree(tree_node("root", true), Vec::new(), 0).unwrap();
+        assert_eq!(tree_shape(&tree), "root()");
+    }
+
+    #[test]
+    fn build_tree_limits() {
+        let nodes = vec![
+            (1, tree_node("a", false)),
+            (1, tree_node("b", false)),
+            (1, tree_node("c", false)),
+        ];
+        let res = build_tree(tree_node("root", true), nodes.into_iter().map(Ok), 2);
+        assert!(res.is_err());
+
+        let nodes = vec![Ok((1, tree_node("a", false))), Err(Error::NotFound)];
+        let res = build_tree(tree_node("root", true), nodes, 10);
+        assert!(matches!(res, Err(Error::NotFound)));
+    }
+}
 
 // vim: fen:fdm=marker:fdl=1:
@@ -0,0 +1,567 @@
+// smbc is library wrapping libsmbclient from Samba project
+// Copyright (c) 2016 Konstantin Gribov
+//
+// This file is part of smbc.
+//
+// smbc is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// smbc is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with smbc. If not, see <http://www.gnu.org/licenses/>.
+
+//! Mirroring directory trees between local filesystem and SMB share
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use result::{Error, Result};
+use smbc::{EntryType, FileType, Progress, SmbClient, TransferOptions, TreeTransfer};
+use url::percent_encode_to;
+
+/// Chunk size used to compare file contents
+const COMPARE_BUF_SIZE: usize = 256 * 1024;
+
+/// Which side of sync is source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncDirection {
+    /// Local tree is copied to SMB share
+    Upload,
+    /// SMB tree is copied to local filesystem
+    Download,
+}
+
+/// Describes how trees are compared and synced
+/// by [`sync(..)`](struct.SmbClient.html#method.sync).
+///
+/// By default new and changed files are copied, extraneous ones are kept.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SyncOptions {
+    delete: bool,
+    checksum: bool,
+    transfer: TransferOptions,
+}
+
+/// What should be done to destination entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncAction {
+    /// Create missing directory
+    CreateDir,
+    /// Copy file missing in destination
+    Create,
+    /// Overwrite file which differs from source
+    Update,
+    /// Remove file missing in source
+    Delete,
+    /// Remove directory missing in source with all its content
+    DeleteDir,
+}
+
+/// Single change of destination tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyncChange {
+    action: SyncAction,
+    path: String,
+    size: u64,
+}
+
+/// Changes making destination tree the same as source,
+/// computed by [`sync_plan(..)`](struct.SmbClient.html#method.sync_plan).
+#[derive(Clone, Debug)]
+pub struct ChangeSet {
+    direction: SyncDirection,
+    local: PathBuf,
+    remote: String,
+    options: SyncOptions,
+    changes: Vec<SyncChange>,
+}
+
+/// File or directory found in tree
+#[derive(Clone, Copy, Debug)]
+struct TreeEntry {
+    dir: bool,
+    size: u64,
+    /// Modification time in whole seconds, finer precision isn't preserved
+    modified: i64,
+}
+
+type Tree = BTreeMap<String, TreeEntry>;
+
+// SyncOptions {{{1
+impl SyncOptions {
+    // {{{2
+    /// Remove destination entries missing in source (disabled by default).
+    ///
+    /// Also allows replacing file with directory and vice versa.
+    pub fn delete(mut self, delete: bool) -> Self {
+        self.delete = delete;
+        self
+    }
+
+    /// Compare files of the same size by content instead of modification time,
+    /// like `rsync --checksum` (disabled by default).
+    ///
+    /// Both copies of every such file are read completely.
+    pub fn checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Options used to copy files.
+    ///
+    /// Modification time is always preserved, otherwise next sync
+    /// would copy the same files again.
+    pub fn transfer_options(mut self, transfer: TransferOptions) -> Self {
+        self.transfer = transfer;
+        self
+    }
+} // 2}}}
+  // 1}}}
+
+// SyncChange {{{1
+impl SyncChange {
+    // {{{2
+    /// What should be done.
+    pub fn action(&self) -> SyncAction {
+        self.action
+    }
+
+    /// Path relative to tree root, components are separated with `/`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Size of source file to copy, `0` for directories and deletions.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+} // 2}}}
+  // 1}}}
+
+// ChangeSet {{{1
+impl ChangeSet {
+    // {{{2
+    pub(crate) fn new(
+        smbc: &SmbClient,
+        local: &Path,
+        remote: &str,
+        direction: SyncDirection,
+        options: SyncOptions,
+    ) -> Result<ChangeSet> {
+        let remote = remote.trim_end_matches('/');
+        trace!(target: "smbc", "comparing {:?} and {} ({:?})", local, remote, direction);
+        let (src, dst) = match direction {
+            SyncDirection::Upload => (list_local(local)?, list_remote(smbc, remote)?),
+            SyncDirection::Download => (list_remote(smbc, remote)?, list_local(local)?),
+        };
+
+        let mut set = ChangeSet {
+            direction,
+            local: local.to_owned(),
+            remote: remote.to_owned(),
+            options,
+            changes: Vec::new(),
+        };
+        let changes = plan(&src, &dst, options.delete, |path, src, dst| {
+            set.differs(smbc, path, src, dst)
+        })?;
+        set.changes = changes;
+        Ok(set)
+    }
+
+    /// Changes in order they are applied, deletions go first.
+    pub fn changes(&self) -> &[SyncChange] {
+        &self.changes
+    }
+
+    /// Check if trees are already in sync.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Which side is source.
+    pub fn direction(&self) -> SyncDirection {
+        self.direction
+    }
+
+    /// Apply changes with `smbc` (usually the client which computed them).
+    ///
+    /// Destination root is created if missing, failure to do it is returned
+    /// as error. Other failures don't stop sync and are collected the same way as in
+    /// [`download_tree(..)`](struct.SmbClient.html#method.download_tree).
+    pub fn apply(&self, smbc: &SmbClient) -> Result<TreeTransfer> {
+        self.apply_with_progress(smbc, |_| {})
+    }
+
+    /// Same as [`apply(..)`](struct.ChangeSet.html#method.apply) calling
+    /// `progress` after each chunk of every file.
+    ///
+    /// Totals are computed from sizes of planned changes.
+    pub fn apply_with_progress<F>(&self, smbc: &SmbClient, mut progress: F) -> Result<TreeTransfer>
+    where
+        F: FnMut(&Progress),
+    {
+        let transfer = self.options.transfer.preserve_mtime(true);
+        let copies = self
+            .changes
+            .iter()
+            .filter(|change| matches!(change.action, SyncAction::Create | SyncAction::Update));
+        let total_files = Some(copies.clone().count() as u64);
+        let total_bytes = Some(copies.map(|change| change.size).sum());
+        let mut report = TreeTransfer::default();
+        match self.direction {
+            SyncDirection::Upload => smbc.create_dir_all(&self.remote)?,
+            SyncDirection::Download => fs::create_dir_all(&self.local)?,
+        }
+
+        for change in &self.changes {
+            let local = self.local.join(&change.path);
+            let remote = self.remote_path(&change.path);
+            trace!(target: "smbc", "sync {:?} {}", change.action, change.path);
+            let res = match (self.direction, change.action) {
+                (SyncDirection::Upload, SyncAction::CreateDir) => {
+                    smbc.create_dir(&remote).map(|_| report.dirs += 1)
+                }
+                (SyncDirection::Upload, SyncAction::Create)
+                | (SyncDirection::Upload, SyncAction::Update) => smbc
+                    .upload_from_with_progress(&local, &remote, transfer, |file| {
+                        progress(&file.within(&report, total_bytes, total_files))
+                    })
+                    .map(|bytes| {
+                        report.files += 1;
+                        report.bytes += bytes;
+                    }),
+                (SyncDirection::Upload, SyncAction::Delete) => smbc.remove_file(&remote),
+                (SyncDirection::Upload, SyncAction::DeleteDir) => smbc.remove_dir_all(&remote),
+                (SyncDirection::Download, SyncAction::CreateDir) => fs::create_dir(&local)
+                    .map(|_| report.dirs += 1)
+                    .map_err(Error::from),
+                (SyncDirection::Download, SyncAction::Create)
+                | (SyncDirection::Download, SyncAction::Update) => smbc
+                    .download_to_with_progress(&remote, &local, transfer, |file| {
+                        progress(&file.within(&report, total_bytes, total_files))
+                    })
+                    .map(|bytes| {
+                        report.files += 1;
+                        report.bytes += bytes;
+                    }),
+                (SyncDirection::Download, SyncAction::Delete) => {
+                    fs::remove_file(&local).map_err(Error::from)
+                }
+                (SyncDirection::Download, SyncAction::DeleteDir) => {
+                    fs::remove_dir_all(&local).map_err(Error::from)
+                }
+            };
+            if let Err(err) = res {
+                let path = match self.direction {
+                    SyncDirection::Upload => remote,
+                    SyncDirection::Download => local.display().to_string(),
+                };
+                report.errors.push(Error::Path(path, Box::new(err)));
+            }
+        }
+        Ok(report)
+    }
+
+    /// SMB URL of `path` relative to remote root
+    fn remote_path(&self, path: &str) -> String {
+        let mut url = self.remote.clone();
+        for component in path.split('/') {
+            url.push('/');
+            percent_encode_to(component, &mut url);
+        }
+        url
+    }
+
+    /// Check if file at `path` should be copied
+    fn differs(
+        &self,
+        smbc: &SmbClient,
+        path: &str,
+        src: &TreeEntry,
+        dst: &TreeEntry,
+    ) -> Result<bool> {
+        if src.size != dst.size {
+            return Ok(true);
+        }
+        if !self.options.checksum {
+            return Ok(src.modified != dst.modified);
+        }
+        let remote = self.remote_path(path);
+        let mut remote_file = smbc.open(&remote)?;
+        let local = self.local.join(path);
+        let mut local_file = fs::File::open(&local)
+            .map_err(|err| Error::Path(local.display().to_string(), Box::new(err.into())))?;
+        let same = same_content(&mut remote_file, &mut local_file)
+            .map_err(|err| Error::Path(remote, Box::new(err.into())))?;
+        Ok(!same)
+    }
+} // 2}}}
+  // 1}}}
+
+/// Changes making `dst` tree the same as `src`, deletions go first.
+///
+/// `differs` tells if file present in both trees should be copied.
+fn plan<F>(src: &Tree, dst: &Tree, delete: bool, mut differs: F) -> Result<Vec<SyncChange>>
+where
+    F: FnMut(&str, &TreeEntry, &TreeEntry) -> Result<bool>,
+{
+    let mut changes = Vec::new();
+    let mut push = |action, path: &str, size| {
+        changes.push(SyncChange {
+            action,
+            path: path.to_owned(),
+            size,
+        })
+    };
+    // every deleted directory is kept: with "a" deleted "a b" sorts before "a/x"
+    let mut deleted: Vec<&str> = Vec::new();
+    for (path, entry) in dst {
+        // content of deleted directory goes with it
+        let in_deleted = deleted
+            .iter()
+            .any(|dir| path.starts_with(dir) && path[dir.len()..].starts_with('/'));
+        if in_deleted {
+            continue;
+        }
+        let conflict = match src.get(path) {
+            Some(src) => src.dir != entry.dir,
+            None => true,
+        };
+        if conflict && delete {
+            if entry.dir {
+                push(SyncAction::DeleteDir, path, 0);
+                deleted.push(path);
+            } else {
+                push(SyncAction::Delete, path, 0);
+            }
+        }
+    }
+    for (path, entry) in src {
+        let action = match dst.get(path) {
+            None if entry.dir => SyncAction::CreateDir,
+            None => SyncAction::Create,
+            Some(dst) if dst.dir != entry.dir => {
+                if !delete {
+                    continue;
+                }
+                if entry.dir {
+                    SyncAction::CreateDir
+                } else {
+                    SyncAction::Create
+                }
+            }
+            Some(_) if entry.dir => continue,
+            Some(dst) => {
+                if !differs(path, entry, dst)? {
+                    continue;
+                }
+                SyncAction::Update
+            }
+        };
+        let size = if entry.dir { 0 } else { entry.size };
+        push(action, path, size);
+    }
+    Ok(changes)
+}
+
+/// Whole seconds since epoch, negative before it
+fn to_secs(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
+
+/// Files and directories under local `root` with paths relative to it,
+/// empty if `root` doesn't exist
+fn list_local(root: &Path) -> Result<Tree> {
+    let mut tree = Tree::new();
+    match fs::metadata(root) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+        _ => list_local_dir(root, "", &mut tree)?,
+    }
+    Ok(tree)
+}
+
+fn list_local_dir(dir: &Path, prefix: &str, tree: &mut Tree) -> Result<()> {
+    let at_dir = |err: io::Error| Error::Path(dir.display().to_string(), Box::new(err.into()));
+    for entry in fs::read_dir(dir).map_err(at_dir)? {
+        let entry = entry.map_err(at_dir)?;
+        let local = entry.path();
+        let name = match entry.file_name().to_str() {
+            Some(name) => format!("{}{}", prefix, name),
+            None => {
+                let err = io::Error::new(io::ErrorKind::InvalidData, "non UTF-8 file name");
+                return Err(Error::Path(
+                    local.display().to_string(),
+                    Box::new(err.into()),
+                ));
+            }
+        };
+        let metadata = entry
+            .metadata()
+            .map_err(|err| Error::Path(local.display().to_string(), Box::new(err.into())))?;
+        // symbolic links and special files are skipped
+        let file_type = metadata.file_type();
+        if !file_type.is_dir() && !file_type.is_file() {
+            continue;
+        }
+        tree.insert(
+            name.clone(),
+            TreeEntry {
+                dir: file_type.is_dir(),
+                size: metadata.len(),
+                modified: to_secs(metadata.modified()?),
+            },
+        );
+        if file_type.is_dir() {
+            list_local_dir(&local, &format!("{}/", name), tree)?;
+        }
+    }
+    Ok(())
+}
+
+/// Files and directories under SMB `root` with paths relative to it,
+/// empty if `root` doesn't exist
+fn list_remote(smbc: &SmbClient, root: &str) -> Result<Tree> {
+    let mut tree = Tree::new();
+    if !smbc.exists(root)? {
+        return Ok(tree);
+    }
+    let mut names: Vec<String> = Vec::new();
+    for entry in smbc.walk(root) {
+        let (depth, entry, metadata) = entry?;
+        names.truncate(depth - 1);
+        names.push(entry.name().to_owned());
+        let dir = match entry.entry_type() {
+            EntryType::Dir if metadata.file_type() != FileType::Symlink => true,
+            EntryType::File => false,
+            _ => continue,
+        };
+        tree.insert(
+            names.join("/"),
+            TreeEntry {
+                dir,
+                size: metadata.len(),
+                modified: to_secs(metadata.modified()),
+            },
+        );
+    }
+    Ok(tree)
+}
+
+/// Check if `a` and `b` have the same content reading them in chunks
+pub(crate) fn same_content<A: Read, B: Read>(a: &mut A, b: &mut B) -> io::Result<bool> {
+    let mut buf_a = vec![0u8; COMPARE_BUF_SIZE];
+    let mut buf_b = vec![0u8; COMPARE_BUF_SIZE];
+    loop {
+        let len_a = fill(a, &mut buf_a)?;
+        let len_b = fill(b, &mut buf_b)?;
+        if buf_a[..len_a] != buf_b[..len_b] {
+            return Ok(false);
+        }
+        if len_a < buf_a.len() {
+            return Ok(true);
+        }
+    }
+}
+
+/// Read until `buf` is full or EOF returning bytes read
+fn fill<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match r.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(entries: &[(&str, bool, u64)]) -> Tree {
+        entries
+            .iter()
+            .map(|&(path, dir, size)| {
+                let entry = TreeEntry {
+                    dir,
+                    size,
+                    modified: 0,
+                };
+                (path.to_owned(), entry)
+            })
+            .collect()
+    }
+
+    fn actions(changes: &[SyncChange]) -> Vec<(SyncAction, &str)> {
+        changes.iter().map(|c| (c.action(), c.path())).collect()
+    }
+
+    #[test]
+    fn plan_skips_content_of_every_deleted_dir() {
+        let src = tree(&[]);
+        // "a b" sorts between "a" and "a/x"
+        let dst = tree(&[("a", true, 0), ("a b", false, 1), ("a/x", false, 1)]);
+        let changes = plan(&src, &dst, true, |_, _, _| Ok(true)).unwrap();
+        assert_eq!(
+            actions(&changes),
+            vec![(SyncAction::DeleteDir, "a"), (SyncAction::Delete, "a b")]
+        );
+    }
+
+    #[test]
+    fn plan_copies_new_and_changed_files() {
+        let src = tree(&[
+            ("d", true, 0),
+            ("d/new", false, 3),
+            ("same", false, 1),
+            ("changed", false, 2),
+        ]);
+        let dst = tree(&[
+            ("same", false, 1),
+            ("changed", false, 2),
+            ("extra", false, 5),
+        ]);
+        let changes = plan(&src, &dst, false, |path, _, _| Ok(path == "changed")).unwrap();
+        assert_eq!(
+            actions(&changes),
+            vec![
+                (SyncAction::Update, "changed"),
+                (SyncAction::CreateDir, "d"),
+                (SyncAction::Create, "d/new"),
+            ]
+        );
+        assert_eq!(changes[2].size(), 3);
+    }
+
+    #[test]
+    fn plan_replaces_type_only_with_delete() {
+        let src = tree(&[("x", true, 0)]);
+        let dst = tree(&[("x", false, 1)]);
+        assert!(plan(&src, &dst, false, |_, _, _| Ok(true))
+            .unwrap()
+            .is_empty());
+        let changes = plan(&src, &dst, true, |_, _, _| Ok(true)).unwrap();
+        assert_eq!(
+            actions(&changes),
+            vec![(SyncAction::Delete, "x"), (SyncAction::CreateDir, "x")]
+        );
+    }
+}
+
+// vim: fen:fdm=marker:fdl=1:
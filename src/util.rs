@@ -19,9 +19,12 @@
 use libc::{c_char, c_int};
 
 use std::borrow::Cow;
+use std::cmp;
 use std::ffi::{CStr, CString};
 use std::io::{self, Write};
 use std::slice;
+use std::thread;
+use std::time::Duration;
 
 use result::*;
 
@@ -36,6 +39,19 @@ pub fn result_from_ptr_mut<T>(ptr: *mut T) -> io::Result<*mut T> {
 }
 
 #[inline(always)]
+/// Ok(ptr) for valid file handle or Err(last_os_error) otherwise
+///
+/// Besides `NULL` some `libsmbclient` versions return `(SMBCFILE *)-1` on failure.
+pub fn result_from_handle<T>(ptr: *mut T) -> io::Result<*mut T> {
+    if ptr.is_null() || (ptr as isize) < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ptr)
+    }
+}
+
+#[inline(always)]
+#[allow(dead_code)]
 /// Ok(ptr) for non-null ptr or Err(last_os_error) otherwise
 pub fn result_from_ptr<T>(ptr: *const T) -> io::Result<*const T> {
     if ptr.is_null() {
@@ -53,9 +69,46 @@ pub fn cstring<P: AsRef<str>>(p: P) -> Result<CString> {
     Ok(CString::new(p.as_ref())?)
 }
 
+/// C string of SMB URL `url` normalized by `normalize_url`
+pub fn smb_cstring<P: AsRef<str>>(url: P) -> Result<CString> {
+    cstring(normalize_url(url.as_ref())?)
+}
+
+/// Check that `url` has `smb://` scheme (in any case) and collapse repeated slashes in its path.
+///
+/// Scheme is lowercased, `libsmbclient` doesn't recognize e.g. `SMB://`.
+pub fn normalize_url<'a>(url: &'a str) -> Result<Cow<'a, str>> {
+    const SCHEME: &str = "smb://";
+    let has_scheme = match url.get(..SCHEME.len()) {
+        Some(scheme) => scheme.eq_ignore_ascii_case(SCHEME),
+        None => false,
+    };
+    if !has_scheme {
+        let msg = format!("{:?} doesn't start with {}", url, SCHEME);
+        return Err(Error::InvalidUrl(msg));
+    }
+    let path_start = url[SCHEME.len()..]
+        .find('/')
+        .map_or(url.len(), |idx| SCHEME.len() + idx);
+    if url.starts_with(SCHEME) && !url[path_start..].contains("//") {
+        return Ok(Cow::Borrowed(url));
+    }
+
+    let mut normalized = String::with_capacity(url.len());
+    normalized.push_str(SCHEME);
+    normalized.push_str(&url[SCHEME.len()..path_start]);
+    for c in url[path_start..].chars() {
+        if c != '/' || !normalized.ends_with('/') {
+            normalized.push(c);
+        }
+    }
+    trace!(target: "smbc", "normalized {:?} to {:?}", url, normalized);
+    Ok(Cow::Owned(normalized))
+}
+
 pub unsafe fn write_to_cstr(dest: *mut u8, len: usize, src: &str) {
     // just to ensure that it can be interpreted as c string
-    *(dest.offset((len - 1) as isize)) = 0u8;
+    *(dest.add(len - 1)) = 0u8;
     trace!(target: "smbc", "orig: {:?}", cstr(dest));
 
     let mut buf = slice::from_raw_parts_mut(dest, len);
@@ -70,6 +123,12 @@ pub unsafe fn write_to_cstr(dest: *mut u8, len: usize, src: &str) {
     trace!(target: "smbc", "write to [{:p};{}] from [{:p},{}]: {:?}", dest, len, src.as_ptr(), src.len(), cstr(dest));
 }
 
+/// Duration in milliseconds clamped to `c_int` range
+#[allow(clippy::legacy_numeric_constants)] // associated consts require Rust 1.43
+pub fn to_millis(d: Duration) -> c_int {
+    cmp::min(d.as_millis(), c_int::max_value() as u128) as c_int
+}
+
 #[inline(always)]
 /// to io::Result with Err(last_os_error) if t == -1
 pub fn to_result_with_le<T: Eq + From<i8>>(t: T) -> io::Result<T> {
@@ -79,7 +138,7 @@ pub fn to_result_with_le<T: Eq + From<i8>>(t: T) -> io::Result<T> {
 #[inline(always)]
 /// to io::Result with Err(from_raw_os_error(errno)) if t == -1
 pub fn to_result_with_errno<T: Eq + From<i8>>(t: T, errno: c_int) -> io::Result<T> {
-    to_result_with_error(t, io::Error::from_raw_os_error(errno as i32))
+    to_result_with_error(t, io::Error::from_raw_os_error(errno))
 }
 
 #[inline(always)]
@@ -90,3 +149,128 @@ fn to_result_with_error<T: Eq + From<i8>>(t: T, err: io::Error) -> io::Result<T>
         Ok(t)
     }
 }
+
+/// Call `f` up to `max_attempts` times while it fails with error `is_transient` accepts.
+///
+/// Sleeps `base_delay` before first retry doubling it on each next one.
+pub fn retry_with_backoff<T, F, C>(
+    max_attempts: u32,
+    base_delay: Duration,
+    is_transient: C,
+    mut f: F,
+) -> Result<T>
+where
+    F: FnMut() -> Result<T>,
+    C: Fn(&Error) -> bool,
+{
+    let mut delay = base_delay;
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(ref err) if attempt < max_attempts && is_transient(err) => {
+                trace!(target: "smbc", "attempt {} failed: {}, retrying in {:?}", attempt, err, delay);
+                thread::sleep(delay);
+                delay = delay.checked_mul(2).unwrap_or(delay);
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn normalize_url_collapses_slashes() {
+        let url = "smb://host/share/dir/file";
+        assert!(matches!(normalize_url(url).unwrap(), Cow::Borrowed(u) if u == url));
+        assert_eq!(normalize_url("smb://host").unwrap(), "smb://host");
+        assert_eq!(
+            normalize_url("smb://host//share///dir//").unwrap(),
+            "smb://host/share/dir/"
+        );
+        // host part may be empty to browse network
+        assert_eq!(normalize_url("smb://").unwrap(), "smb://");
+    }
+
+    #[test]
+    fn normalize_url_scheme_is_case_insensitive() {
+        assert_eq!(
+            normalize_url("SMB://host/share").unwrap(),
+            "smb://host/share"
+        );
+        assert_eq!(
+            normalize_url("Smb://host//share").unwrap(),
+            "smb://host/share"
+        );
+        assert_eq!(normalize_url("sMb://Host").unwrap(), "smb://Host");
+    }
+
+    #[test]
+    fn normalize_url_rejects_other_schemes() {
+        for url in &[
+            "",
+            "smb:/host",
+            "http://host/share",
+            "/share/file",
+            "smbé://h",
+        ] {
+            assert!(
+                matches!(normalize_url(url), Err(Error::InvalidUrl(_))),
+                "{}",
+                url
+            );
+        }
+    }
+
+    fn transient(err: &Error) -> bool {
+        match *err {
+            Error::Io(ref err) => err.kind() == io::ErrorKind::Interrupted,
+            _ => false,
+        }
+    }
+
+    fn interrupted() -> Error {
+        io::Error::new(io::ErrorKind::Interrupted, "busy").into()
+    }
+
+    #[test]
+    fn retry_stops_after_max_attempts() {
+        let calls = Cell::new(0);
+        let res: Result<()> = retry_with_backoff(3, Duration::from_millis(0), transient, || {
+            calls.set(calls.get() + 1);
+            Err(interrupted())
+        });
+        assert!(res.is_err());
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn retry_stops_on_permanent_error() {
+        let calls = Cell::new(0);
+        let res: Result<()> = retry_with_backoff(5, Duration::from_millis(0), transient, || {
+            calls.set(calls.get() + 1);
+            Err(Error::NotFound)
+        });
+        assert!(matches!(res, Err(Error::NotFound)));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn retry_returns_first_success() {
+        let calls = Cell::new(0);
+        let res = retry_with_backoff(5, Duration::from_millis(0), transient, || {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 {
+                Err(interrupted())
+            } else {
+                Ok(calls.get())
+            }
+        });
+        assert_eq!(res.unwrap(), 3);
+        assert_eq!(calls.get(), 3);
+    }
+}